@@ -66,7 +66,7 @@ viewHeader session =
                 [ a
                     [ class "navbar-brand", href "/" ]
                     [ img
-                        [ src "img/logo-color.png"
+                        [ src "/img/logo-color.png"
                         , alt "Logo"
                         , width 30
                         , height 24
@@ -0,0 +1 @@
+ALTER TABLE account ADD disabled BOOLEAN DEFAULT false NOT NULL;
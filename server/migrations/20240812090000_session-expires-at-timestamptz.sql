@@ -0,0 +1,4 @@
+-- The default wrote local times of the database session time zone, which the cast reads back.
+ALTER TABLE session
+    ALTER COLUMN expires_at TYPE TIMESTAMPTZ USING expires_at::timestamptz,
+    ALTER COLUMN expires_at SET DEFAULT current_timestamp + (30 * INTERVAL '1 minute');
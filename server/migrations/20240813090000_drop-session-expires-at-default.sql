@@ -0,0 +1,2 @@
+-- Expiry times are computed by the server's clock and passed in with every insert.
+ALTER TABLE session ALTER COLUMN expires_at DROP DEFAULT;
@@ -0,0 +1,137 @@
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{Error, HttpMessage};
+use futures_util::future::LocalBoxFuture;
+use log::{log, Level};
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::time::Instant;
+use uuid::Uuid;
+
+use crate::api_error::ApiErrorType;
+use crate::authorisation::AccountId;
+use crate::configuration::AccessLogSettings;
+use crate::logging::ACCESS_TARGET;
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Id of the request, taken from the `X-Request-Id` header or generated. It is echoed in the
+/// response header so that client side errors can be matched with log lines.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+/// Writes one line per request to the access log.
+pub struct AccessLog {
+    settings: Rc<AccessLogSettings>,
+}
+
+impl AccessLog {
+    pub fn new(settings: AccessLogSettings) -> Self {
+        AccessLog {
+            settings: Rc::new(settings),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AccessLog
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static + MessageBody,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = AccessLogMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AccessLogMiddleware {
+            service: service.into(),
+            settings: self.settings.clone(),
+        }))
+    }
+}
+
+pub struct AccessLogMiddleware<S> {
+    service: Rc<S>,
+    settings: Rc<AccessLogSettings>,
+}
+
+impl<S, B> Service<ServiceRequest> for AccessLogMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static + MessageBody,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+        let excluded = !self.settings.enabled
+            || self
+                .settings
+                .exclude
+                .iter()
+                .any(|prefix| req.path().starts_with(prefix.as_str()));
+
+        let request_id = match req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(value) if !value.is_empty() && value.len() <= 64 => value.to_string(),
+            _ => Uuid::new_v4().to_string(),
+        };
+        req.extensions_mut().insert(RequestId(request_id.clone()));
+
+        Box::pin(async move {
+            let start = Instant::now();
+            let method = req.method().to_string();
+            let path = req.path().to_owned();
+            let ip = req.peer_addr().map(|addr| addr.ip().to_string());
+
+            let mut res = srv.call(req).await?;
+            if let Ok(value) = HeaderValue::from_str(&request_id) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            if excluded {
+                return Ok(res);
+            }
+
+            let request = res.request();
+            let route = request.match_pattern().unwrap_or(path);
+            // the stable code only, details of unexpected errors stay in the application log
+            let error = ApiErrorType::of_request(request).map_or("-", |error| error.code());
+            let bytes = match res.response().body().size() {
+                BodySize::Sized(size) => size.to_string(),
+                _ => "-".to_string(),
+            };
+            let account_id = match request.extensions().get::<AccountId>() {
+                Some(account_id) => account_id.to_string(),
+                None => "-".to_string(),
+            };
+            log!(
+                target: ACCESS_TARGET,
+                Level::Info,
+                "{} {} {} \"{}\" {}ms {} {} {} {}",
+                method,
+                route,
+                res.status().as_u16(),
+                error,
+                start.elapsed().as_millis(),
+                bytes,
+                ip.unwrap_or("-".to_string()),
+                request_id,
+                account_id
+            );
+            Ok(res)
+        })
+    }
+}
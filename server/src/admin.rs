@@ -0,0 +1,299 @@
+use anyhow::{anyhow, bail, Context};
+use base64::engine::general_purpose;
+use base64::Engine;
+use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::{query, query_scalar, PgPool};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::cli::{AccountCommand, Command, SecretCommand, SessionCommand};
+use crate::clock::Clock;
+use crate::configuration::Settings;
+use crate::repository::{SessionBackend, SessionStore};
+use crate::routes::hash_password;
+use crate::validation::{AccountName, AccountPassword};
+use crate::{database, repository};
+
+/// Length of generated session secrets in bytes.
+const SECRET_LENGTH: usize = 32;
+/// Longest name shown to the user the `account` table allows.
+const MAX_NAME_LENGTH: usize = 80;
+
+/// Prints a random `session_secret`, which doesn't need the configuration.
+pub fn generate_secret(command: &SecretCommand) {
+    match command {
+        SecretCommand::Generate { base64 } => {
+            let secret: [u8; SECRET_LENGTH] = rand::random();
+            if *base64 {
+                println!("base64:{}", general_purpose::STANDARD.encode(secret));
+            } else {
+                println!("{}", hex::encode(secret));
+            }
+        }
+    }
+}
+
+/// Runs one of the administrative commands against the database and session store of
+/// `settings`, deciding which sessions have expired by `clock`.
+pub async fn run(
+    command: Command,
+    settings: &Settings,
+    clock: Arc<dyn Clock>,
+) -> anyhow::Result<()> {
+    let db_pool = database::connect(&settings.database)
+        .await
+        .context("Couldn't connect to database")?;
+    let now = clock.now();
+    match command {
+        Command::Account(command) => account(command, settings, &db_pool, now).await,
+        Command::Session(command) => session(command, settings, &db_pool, now).await,
+        Command::Migrate => {
+            let applied = database::migrate(&db_pool).await?;
+            println!(
+                "Applied {} migration(s), database schema is up to date.",
+                applied
+            );
+            Ok(())
+        }
+        Command::Serve | Command::CheckConfig | Command::Secret(_) => {
+            Err(anyhow!("Not an administrative command"))
+        }
+    }
+}
+
+/// The session store the server uses. Sessions of the `memory` backend only exist in the server
+/// process, so commands needing them fail rather than finding none.
+async fn session_store(
+    settings: &Settings,
+    db_pool: &PgPool,
+) -> anyhow::Result<Arc<dyn SessionStore>> {
+    if settings.sessions.backend == SessionBackend::Memory {
+        bail!(
+            "Sessions of the `memory` backend only exist in the server process and can't be \
+             managed by commands, restart the server to delete them"
+        );
+    }
+    repository::session_store(&settings.sessions, db_pool).await
+}
+
+async fn account(
+    command: AccountCommand,
+    settings: &Settings,
+    db_pool: &PgPool,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    match command {
+        AccountCommand::Create {
+            account_name,
+            name,
+            password_stdin,
+        } => {
+            let account_name = parse_account_name(account_name)?;
+            let name = name.trim();
+            if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+                bail!(
+                    "Name must have between 1 and {} characters",
+                    MAX_NAME_LENGTH
+                );
+            }
+            let pw_hash = hash_password(&read_password(password_stdin)?)?;
+            query!(
+                // language=postgresql
+                r#"
+                    INSERT INTO account (account_name, pw_hash, name) VALUES ($1, $2, $3)
+                "#,
+                account_name.as_ref(),
+                pw_hash,
+                name
+            )
+            .execute(db_pool)
+            .await
+            .context("Couldn't create account, the account name may be taken")?;
+            println!("Created account {}.", account_name.as_ref());
+        }
+        AccountCommand::SetPassword {
+            account_name,
+            password_stdin,
+        } => {
+            let account_name = parse_account_name(account_name)?;
+            let sessions = session_store(settings, db_pool).await?;
+            let pw_hash = hash_password(&read_password(password_stdin)?)?;
+            let account_id = query_scalar!(
+                // language=postgresql
+                r#"
+                    UPDATE account SET pw_hash = $2 WHERE account_name = $1 RETURNING id
+                "#,
+                account_name.as_ref(),
+                pw_hash
+            )
+            .fetch_optional(db_pool)
+            .await?;
+            let Some(account_id) = account_id else {
+                bail!("There is no account {}", account_name.as_ref());
+            };
+            let deleted = sessions.delete_for_account(account_id).await?;
+            println!(
+                "Set password of account {}, deleted {} session(s).",
+                account_name.as_ref(),
+                deleted
+            );
+        }
+        AccountCommand::Disable { account_name } => {
+            let account_name = parse_account_name(account_name)?;
+            let sessions = session_store(settings, db_pool).await?;
+            let account_id = query_scalar!(
+                // language=postgresql
+                r#"
+                    UPDATE account SET disabled = true WHERE account_name = $1 RETURNING id
+                "#,
+                account_name.as_ref()
+            )
+            .fetch_optional(db_pool)
+            .await?;
+            let Some(account_id) = account_id else {
+                bail!("There is no account {}", account_name.as_ref());
+            };
+            let deleted = sessions.delete_for_account(account_id).await?;
+            println!(
+                "Disabled account {}, deleted {} session(s).",
+                account_name.as_ref(),
+                deleted
+            );
+        }
+        AccountCommand::List => {
+            // the accounts are listed even if their sessions can't be counted
+            let active = match active_sessions(settings, db_pool, now).await {
+                Ok(active) => Some(active),
+                Err(error) => {
+                    eprintln!("Warning: Couldn't count sessions: {:#}", error);
+                    None
+                }
+            };
+            let rows = query!(
+                // language=postgresql
+                r#"
+                    SELECT
+                        id,
+                        account_name,
+                        name,
+                        preferred_language::text AS "preferred_language!",
+                        disabled
+                    FROM account
+                    ORDER BY account_name
+                "#
+            )
+            .fetch_all(db_pool)
+            .await?;
+            println!(
+                "ACCOUNT              NAME                           LANGUAGE DISABLED SESSIONS"
+            );
+            for row in rows {
+                let sessions = match &active {
+                    Some(active) => active.get(&row.id).unwrap_or(&0).to_string(),
+                    None => "-".to_string(),
+                };
+                println!(
+                    "{:<20} {:<30} {:<8} {:<8} {}",
+                    row.account_name, row.name, row.preferred_language, row.disabled, sessions
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Number of unexpired sessions of each account having any.
+async fn active_sessions(
+    settings: &Settings,
+    db_pool: &PgPool,
+    now: DateTime<Utc>,
+) -> anyhow::Result<HashMap<Uuid, usize>> {
+    let mut active = HashMap::new();
+    for session in session_store(settings, db_pool).await?.list().await? {
+        if session.expires_at > now {
+            *active.entry(session.account_id).or_default() += 1;
+        }
+    }
+    Ok(active)
+}
+
+async fn session(
+    command: SessionCommand,
+    settings: &Settings,
+    db_pool: &PgPool,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let sessions = session_store(settings, db_pool).await?;
+    match command {
+        SessionCommand::List => {
+            let account_names: HashMap<Uuid, String> = query!(
+                // language=postgresql
+                r#"
+                    SELECT id, account_name FROM account
+                "#
+            )
+            .fetch_all(db_pool)
+            .await?
+            .into_iter()
+            .map(|row| (row.id, row.account_name))
+            .collect();
+            println!("ID                                   ACCOUNT              EXPIRES AT");
+            for session in sessions.list().await? {
+                let account_name = account_names
+                    .get(&session.account_id)
+                    .map_or("-", String::as_str);
+                println!(
+                    "{:<36} {:<20} {}",
+                    session.id, account_name, session.expires_at
+                );
+            }
+        }
+        SessionCommand::Purge { all, account } => {
+            let deleted = match (all, account) {
+                (true, _) => sessions.delete_all().await?,
+                (false, Some(account_name)) => {
+                    let account_id = query_scalar!(
+                        // language=postgresql
+                        r#"
+                            SELECT id FROM account WHERE account_name = $1
+                        "#,
+                        account_name
+                    )
+                    .fetch_optional(db_pool)
+                    .await?;
+                    let Some(account_id) = account_id else {
+                        bail!("There is no account {}", account_name);
+                    };
+                    sessions.delete_for_account(account_id).await?
+                }
+                (false, None) if settings.sessions.backend == SessionBackend::Redis => {
+                    bail!("The `redis` backend removes expired sessions by itself")
+                }
+                (false, None) => sessions.purge_expired(now).await?,
+            };
+            println!("Deleted {} session(s).", deleted);
+        }
+    }
+    Ok(())
+}
+
+fn parse_account_name(account_name: String) -> anyhow::Result<AccountName> {
+    AccountName::parse(&Some(account_name)).map_err(|error| anyhow!(error.as_ref().clone()))
+}
+
+/// Asks twice for the password on the terminal, or reads it from stdin for scripts.
+fn read_password(from_stdin: bool) -> anyhow::Result<AccountPassword> {
+    let password = if from_stdin {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        line.trim_end_matches(['\r', '\n']).to_string()
+    } else {
+        let password = rpassword::prompt_password("Password: ")?;
+        if rpassword::prompt_password("Repeat password: ")? != password {
+            bail!("Passwords don't match");
+        }
+        password
+    };
+    AccountPassword::parse(&Some(password)).map_err(|error| anyhow!(error.as_ref().clone()))
+}
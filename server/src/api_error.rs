@@ -1,4 +1,5 @@
 use crate::authorisation::{ApiResponse, HandlerResponse};
+use crate::repository::RepositoryError;
 use crate::routes::ExpiresAt;
 use actix_web::body::BoxBody;
 use actix_web::http::StatusCode;
@@ -24,6 +25,29 @@ pub enum ApiErrorType {
     Expired,
 }
 
+impl ApiErrorType {
+    /// The error recorded for the request by a handler or middleware, if any.
+    pub fn of_request(request: &HttpRequest) -> Option<ApiErrorType> {
+        let extensions = request.extensions();
+        match extensions.get::<ApiError>() {
+            Some(api_error) => Some(api_error.error),
+            None => extensions.get::<ApiErrorType>().copied(),
+        }
+    }
+
+    /// Short name of the error that doesn't change with its message, e.g. for metric labels.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiErrorType::BadRequest => "bad_request",
+            ApiErrorType::DbError => "db_error",
+            ApiErrorType::NotFoundError => "not_found",
+            ApiErrorType::Unauthorized => "unauthorized",
+            ApiErrorType::Unexpected(_) => "unexpected",
+            ApiErrorType::Expired => "expired",
+        }
+    }
+}
+
 impl Into<&str> for ApiErrorType {
     fn into(self) -> &'static str {
         match self {
@@ -49,8 +73,8 @@ impl Into<String> for ApiErrorType {
     }
 }
 
-impl From<sqlx::Error> for ApiErrorType {
-    fn from(_: sqlx::Error) -> Self {
+impl From<RepositoryError> for ApiErrorType {
+    fn from(_: RepositoryError) -> Self {
         ApiErrorType::DbError
     }
 }
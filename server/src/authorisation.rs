@@ -9,13 +9,13 @@ use futures_util::future::LocalBoxFuture;
 use log::{log, Level};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use sqlx::types::chrono::Utc;
-use sqlx::{query, PgPool};
 use std::future::{ready, Ready};
 use std::rc::Rc;
 use uuid::Uuid;
 
 use crate::api_error::{ApiError, ApiErrorType};
+use crate::clock::Clock;
+use crate::repository::SessionStore;
 use crate::routes::{ExpiresAt, LoginResponse, SessionResponse};
 
 pub struct Authorisation;
@@ -65,7 +65,8 @@ where
 
         async fn authorize(req: &ServiceRequest) -> Result<ExpiresAt, ApiErrorType> {
             let session_secret = req.app_data::<web::Data<Bytes>>().unwrap();
-            let db_pool = req.app_data::<web::Data<PgPool>>().unwrap();
+            let sessions = req.app_data::<web::Data<dyn SessionStore>>().unwrap();
+            let clock = req.app_data::<web::Data<dyn Clock>>().unwrap();
 
             let authorisation_header = match req.headers().get(header::AUTHORIZATION) {
                 Some(header) => header,
@@ -144,15 +145,7 @@ where
                     return Err(ApiErrorType::Unauthorized);
                 }
             };
-            let session_row = match query!(
-                // language=postgresql
-                r#"
-                    SELECT * FROM session WHERE id = $1
-                "#,
-                session_id
-            )
-            .fetch_optional(&***db_pool)
-            .await {
+            let session_row = match sessions.find(session_id).await {
                 Ok(Some(row)) => row,
                 Ok(None) => {
                     log!(
@@ -162,46 +155,25 @@ where
                         );
                     return Err(ApiErrorType::Unauthorized);
                 }
-                Err(error) => {
-                    log!(
-                    Level::Error,
-                    "Error: {}, while finding session, Data: {:?}",
-                    error,
-                    session_id
-                );
-                    return Err(ApiErrorType::DbError);
-                }
+                Err(_) => return Err(ApiErrorType::DbError),
             };
-            let expired = session_row.expires_at < Utc::now().naive_utc();
+            let now = clock.now();
+            let expired = session_row.expires_at < now;
             if expired {
                 Err(ApiErrorType::Expired)
             } else {
-                let updated_session_row = match query!(
-                    // language=postgresql
-                    r#"
-                        UPDATE session SET expires_at = DEFAULT
-                            WHERE id = $1 RETURNING id, expires_at
-                    "#,
-                    session_id
-                )
-                .fetch_one(&***db_pool)
-                .await {
-                    Ok(row) => row,
-                    Err(error) => {
-                        log!(
-                    Level::Error,
-                    "Error: {}, while updating session, Data: {:?}",
-                    error,
-                    session_id
-                );
-                        return Err(ApiErrorType::DbError);
-                    }
+                let updated_session_row = match sessions.refresh(&session_row, now).await {
+                    Ok(Some(row)) => row,
+                    Ok(None) => return Err(ApiErrorType::Unauthorized),
+                    Err(_) => return Err(ApiErrorType::DbError),
                 };
 
                 req.extensions_mut()
                     .insert(DBId(updated_session_row.id));
+                req.extensions_mut()
+                    .insert(AccountId(updated_session_row.account_id));
 
-                Ok(updated_session_row.expires_at.and_utc().timestamp() as ExpiresAt)
+                Ok(updated_session_row.expires_at.timestamp() as ExpiresAt)
             }
         }
 
@@ -210,9 +182,11 @@ where
             if url_path != "login" {
                 let auth_result = authorize(&req).await;
                 if auth_result.is_err() {
+                    let error = auth_result.err().unwrap();
+                    req.extensions_mut().insert(error);
                     let new_body = ApiResponse {
                         expires_at: 0,
-                        error: auth_result.err().unwrap().into(),
+                        error: error.into(),
                         data: HandlerResponse::None(),
                     };
                     let new_resp = HttpResponse::Ok().json(new_body);
@@ -225,34 +199,6 @@ where
                 expires_at = 0;
             }
 
-            //deleting outdated
-            let db_pool = req.app_data::<web::Data<PgPool>>().unwrap();
-            match query!(
-                // language=postgresql
-                r#"
-                        DELETE FROM session WHERE expires_at < CURRENT_TIMESTAMP + INTERVAL '20 minutes';
-                "#
-                )
-                .execute(&***db_pool)
-                .await {
-                Ok(_) => (),
-                Err(error) => {
-                    log!(
-                    Level::Error,
-                    "Error: {}, while deleting sessions",
-                    error,
-                );
-                    let new_body = ApiResponse {
-                        expires_at: 0,
-                        error: ApiErrorType::DbError.into(),
-                        data: HandlerResponse::None(),
-                    };
-                    let new_resp = HttpResponse::Ok().json(new_body);
-                    let new_res = ServiceResponse::new(req.request().clone(), new_resp);
-                    return Ok(new_res.map_into_right_body());
-                }
-            }
-
             //call other middleware and handler and get the response
             let res = srv.call(req).await?;
             let request = res.request().clone();
@@ -318,6 +264,33 @@ impl std::ops::Deref for DBId {
     }
 }
 
+/// Id of the account the request is authenticated for.
+#[derive(Clone, Copy, Debug)]
+pub struct AccountId(pub Uuid);
+
+impl FromRequest for AccountId {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let result = match req.extensions().get::<AccountId>() {
+            None => Err(ApiError {
+                req: req.clone(),
+                error: ApiErrorType::Unauthorized,
+            }),
+            Some(account_id) => Ok(*account_id),
+        };
+        ready(result)
+    }
+}
+
+impl std::ops::Deref for AccountId {
+    type Target = Uuid;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub enum HandlerResponse {
     Session(SessionResponse),
@@ -342,3 +315,267 @@ pub struct ApiResponse {
     pub error: String,
     pub data: HandlerResponse,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FakeClock;
+    use crate::configuration::CorsSettings;
+    use crate::cors;
+    use crate::metrics::Metrics;
+    use crate::repository::{
+        Account, AccountRepository, Lang, MemoryAccountRepository, MemorySessionStore, Session,
+    };
+    use crate::routes;
+    use actix_web::{test, App};
+    use chrono::Duration;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    const SESSION_SECRET: &[u8] = b"0123456789abcdef0123456789abcdef";
+    const PASSWORD: &str = "correct horse";
+
+    struct Fixture {
+        accounts: Arc<MemoryAccountRepository>,
+        sessions: Arc<MemorySessionStore>,
+        clock: Arc<FakeClock>,
+        account_id: Uuid,
+    }
+
+    fn fixture() -> Fixture {
+        let accounts = Arc::new(MemoryAccountRepository::default());
+        let account_id = Uuid::new_v4();
+        accounts.insert(Account {
+            id: account_id,
+            account_name: "tester".to_string(),
+            pw_hash: bcrypt::hash(PASSWORD, 4).unwrap(),
+            name: "Test User".to_string(),
+            preferred_lang: Lang::De,
+            disabled: false,
+        });
+        Fixture {
+            accounts,
+            sessions: Arc::new(MemorySessionStore::default()),
+            clock: Arc::new(FakeClock::default()),
+            account_id,
+        }
+    }
+
+    macro_rules! init_api {
+        ($fixture:expr) => {{
+            let accounts: Arc<dyn AccountRepository> = $fixture.accounts.clone();
+            let sessions: Arc<dyn SessionStore> = $fixture.sessions.clone();
+            let clock: Arc<dyn Clock> = $fixture.clock.clone();
+            test::init_service(
+                App::new()
+                    .app_data(web::Data::from(accounts))
+                    .app_data(web::Data::from(sessions))
+                    .app_data(web::Data::from(clock))
+                    .app_data(web::Data::new(Metrics::new().unwrap()))
+                    .service(
+                        web::scope("/api")
+                            .app_data(web::Data::new(Bytes::from_static(SESSION_SECRET)))
+                            .wrap(Authorisation)
+                            .route("/login", web::post().to(routes::login_handler))
+                            .route("/session", web::get().to(routes::session_handler))
+                            .route("/session", web::delete().to(routes::logout_handler)),
+                    ),
+            )
+            .await
+        }};
+    }
+
+    fn request() -> test::TestRequest {
+        test::TestRequest::default().peer_addr("127.0.0.1:4711".parse().unwrap())
+    }
+
+    fn token(session_id: Uuid) -> String {
+        let encrypted = simple_crypt::encrypt(session_id.as_ref(), SESSION_SECRET).unwrap();
+        format!("Bearer {}", general_purpose::URL_SAFE.encode(encrypted))
+    }
+
+    fn insert_session(fixture: &Fixture, expires_in: Duration) -> Uuid {
+        let session_id = Uuid::new_v4();
+        fixture.sessions.insert(Session {
+            id: session_id,
+            account_id: fixture.account_id,
+            expires_at: fixture.clock.now() + expires_in,
+        });
+        session_id
+    }
+
+    #[actix_web::test]
+    async fn login_creates_session() {
+        let fixture = fixture();
+        let api = init_api!(fixture);
+
+        let body: Value = test::call_and_read_body_json(
+            &api,
+            request()
+                .method(actix_web::http::Method::POST)
+                .uri("/api/login")
+                .set_json(json!({ "account": "tester", "pw": PASSWORD }))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(body["error"], "");
+        assert!(body["expires_at"].as_i64().unwrap() > fixture.clock.now().timestamp());
+        assert!(body["data"]["Login"]["session_token"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn login_with_wrong_password_is_refused() {
+        let fixture = fixture();
+        let api = init_api!(fixture);
+
+        let body: Value = test::call_and_read_body_json(
+            &api,
+            request()
+                .method(actix_web::http::Method::POST)
+                .uri("/api/login")
+                .set_json(json!({ "account": "tester", "pw": "wrong password" }))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(body["error"], "Unauthorized");
+        assert_eq!(body["expires_at"], 0);
+    }
+
+    #[actix_web::test]
+    async fn session_returns_account_and_extends_expiry() {
+        let fixture = fixture();
+        let session_id = insert_session(&fixture, Duration::minutes(25));
+        let api = init_api!(fixture);
+
+        let body: Value = test::call_and_read_body_json(
+            &api,
+            request()
+                .uri("/api/session")
+                .insert_header((header::AUTHORIZATION, token(session_id)))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(body["error"], "");
+        assert_eq!(body["data"]["Session"]["name"], "Test User");
+        assert_eq!(body["data"]["Session"]["preferred_lang"], "De");
+        let expires_at = body["expires_at"].as_i64().unwrap();
+        assert_eq!(
+            expires_at,
+            (fixture.clock.now() + Duration::minutes(30)).timestamp()
+        );
+    }
+
+    #[actix_web::test]
+    async fn expired_session_is_refused() {
+        let fixture = fixture();
+        let session_id = insert_session(&fixture, Duration::minutes(-1));
+        let api = init_api!(fixture);
+
+        let body: Value = test::call_and_read_body_json(
+            &api,
+            request()
+                .uri("/api/session")
+                .insert_header((header::AUTHORIZATION, token(session_id)))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(body["error"], "Expired");
+        assert_eq!(body["data"], json!({ "None": [] }));
+    }
+
+    #[actix_web::test]
+    async fn session_expires_once_its_lifetime_has_passed() {
+        let fixture = fixture();
+        let session_id = insert_session(&fixture, Duration::minutes(25));
+        let api = init_api!(fixture);
+        let session = || {
+            request()
+                .uri("/api/session")
+                .insert_header((header::AUTHORIZATION, token(session_id)))
+                .to_request()
+        };
+
+        // each request extends the session to the full lifetime from the clock's time
+        fixture.clock.advance(Duration::minutes(20));
+        let body: Value = test::call_and_read_body_json(&api, session()).await;
+        assert_eq!(body["error"], "");
+
+        fixture.clock.advance(Duration::minutes(31));
+        let body: Value = test::call_and_read_body_json(&api, session()).await;
+        assert_eq!(body["error"], "Expired");
+    }
+
+    #[actix_web::test]
+    async fn logout_deletes_session() {
+        let fixture = fixture();
+        let session_id = insert_session(&fixture, Duration::minutes(25));
+        let api = init_api!(fixture);
+
+        let body: Value = test::call_and_read_body_json(
+            &api,
+            request()
+                .method(actix_web::http::Method::DELETE)
+                .uri("/api/session")
+                .insert_header((header::AUTHORIZATION, token(session_id)))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(body["error"], "");
+        assert!(fixture.sessions.find(session_id).await.unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn cors_preflight_is_not_rejected_as_unauthorised() {
+        let fixture = fixture();
+        let sessions: Arc<dyn SessionStore> = fixture.sessions.clone();
+        let clock: Arc<dyn Clock> = fixture.clock.clone();
+        let cors_settings = CorsSettings {
+            allowed_origins: vec!["http://localhost:8000".to_string()],
+            allowed_methods: vec!["GET".to_string(), "DELETE".to_string()],
+            allowed_headers: vec!["Authorization".to_string()],
+            allow_credentials: false,
+            max_age_seconds: None,
+        };
+        let api = test::init_service(
+            App::new()
+                .app_data(web::Data::from(sessions))
+                .app_data(web::Data::from(clock))
+                .service(
+                    web::scope("/api")
+                        .app_data(web::Data::new(Bytes::from_static(SESSION_SECRET)))
+                        .wrap(Authorisation)
+                        .wrap(cors::cors(&cors_settings))
+                        .route("/session", web::get().to(routes::session_handler)),
+                ),
+        )
+        .await;
+
+        let response = test::call_service(
+            &api,
+            request()
+                .method(actix_web::http::Method::OPTIONS)
+                .uri("/api/session")
+                .insert_header((header::ORIGIN, "http://localhost:8000"))
+                .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "GET"))
+                .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization"))
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .unwrap(),
+            "http://localhost:8000"
+        );
+        let body = test::read_body(response).await;
+        assert!(!String::from_utf8_lossy(&body).contains("Unauthorized"));
+    }
+}
@@ -0,0 +1,95 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Configuration file overriding `config/base.json` and the profile file
+    #[arg(long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+    /// Deprecated spelling of the `check-config` command, kept for existing scripts
+    #[arg(long, hide = true)]
+    pub check_config: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// The command to run, `serve` if none is given.
+    pub fn command(self) -> Command {
+        if self.check_config {
+            eprintln!("Warning: `--check-config` is deprecated, use the `check-config` command");
+            return Command::CheckConfig;
+        }
+        self.command.unwrap_or(Command::Serve)
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the server (default)
+    Serve,
+    /// Manage the accounts able to log in
+    #[command(subcommand)]
+    Account(AccountCommand),
+    /// Inspect and delete sessions
+    #[command(subcommand)]
+    Session(SessionCommand),
+    /// Generate secrets for the configuration
+    #[command(subcommand)]
+    Secret(SecretCommand),
+    /// Apply the pending database migrations and exit
+    Migrate,
+    /// Validate the configuration and exit with a non-zero status if there are problems
+    CheckConfig,
+}
+
+#[derive(Subcommand)]
+pub enum AccountCommand {
+    /// Create an account, asking for its password
+    Create {
+        account_name: String,
+        /// Name shown to the user
+        #[arg(long)]
+        name: String,
+        /// Read the password from the first line of stdin instead of asking for it
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Set the password of an account, logging it out everywhere
+    SetPassword {
+        account_name: String,
+        /// Read the password from the first line of stdin instead of asking for it
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Prevent an account from logging in, logging it out everywhere
+    Disable { account_name: String },
+    /// List all accounts
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum SessionCommand {
+    /// List all sessions, including expired ones not cleaned up yet
+    List,
+    /// Delete expired sessions
+    Purge {
+        /// Delete all sessions, logging out every user
+        #[arg(long, conflicts_with = "account")]
+        all: bool,
+        /// Delete the sessions of this account, expired or not
+        #[arg(long, value_name = "ACCOUNT_NAME")]
+        account: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SecretCommand {
+    /// Print a random `session_secret`
+    Generate {
+        /// Encode as base64 instead of hex
+        #[arg(long)]
+        base64: bool,
+    },
+}
@@ -0,0 +1,48 @@
+use chrono::Duration;
+use sqlx::types::chrono::{DateTime, Utc};
+use std::sync::Mutex;
+
+/// Source of the current time for session expiry and log rotation, registered as
+/// `Data<dyn Clock>`, so that tests can move time instead of sleeping.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The clock of the system.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock standing still until it is advanced, for tests.
+pub struct FakeClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl FakeClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        FakeClock {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Default for FakeClock {
+    /// Starts at the current time of the system.
+    fn default() -> Self {
+        FakeClock::new(Utc::now())
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
@@ -1,40 +1,506 @@
+use actix_web::http::header::HeaderName;
+use actix_web::http::Method;
+use anyhow::{anyhow, Context};
+use base64::engine::general_purpose;
+use base64::Engine;
 use serde::de::Error;
 use serde::{Deserialize, Deserializer};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgSslMode};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Display, Formatter};
+use std::fs::{create_dir_all, remove_file, File};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
-#[derive(Deserialize)]
+use crate::database::MigrationMode;
+use crate::repository::SessionBackend;
+
+/// Directory holding `base.json` and the profile files, relative to the working directory.
+const CONFIG_DIR: &str = "config";
+/// Environment variable selecting the profile.
+const PROFILE_VARIABLE: &str = "APP_PROFILE";
+/// Settings that may instead be read from the file named by `<key>_file`.
+const SECRET_KEYS: [&str; 3] = ["session_secret", "database.password", "sessions.redis_url"];
+/// List settings, given as comma separated values in environment variables.
+const LIST_KEYS: [&str; 3] = ["log.access.exclude", "server.hosts", "cors.allowed_origins"];
+/// The session secret shipped in `config/base.json`, which is public and must not be used in
+/// production.
+const SAMPLE_SESSION_SECRET: &str = "e5545eaf0a1563e269259779f1c940b0";
+const MIN_SESSION_SECRET_LENGTH: usize = 16;
+
+#[derive(Deserialize, Debug)]
 pub struct Settings {
+    pub profile: Profile,
     pub database: DatabaseSettings,
     pub log: LogSettings,
     pub application_port: u16,
-    pub session_secret: Vec<u8>,
+    pub server: ServerSettings,
+    pub static_files: StaticSettings,
+    pub security_headers: SecurityHeadersSettings,
+    pub cors: CorsSettings,
+    #[serde(default)]
+    pub metrics: MetricsSettings,
+    #[serde(default)]
+    pub sessions: SessionSettings,
+    /// Hex encoded, or base64 encoded if prefixed with `base64:`.
+    #[serde(deserialize_with = "string_to_secret_bytes")]
+    pub session_secret: Secret<Vec<u8>>,
+}
+
+impl Settings {
+    /// Checks for problems that would otherwise only show up at first use, reporting all of
+    /// them at once.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if self.application_port == 0 {
+            problems.push("`application_port` must be between 1 and 65535".to_string());
+        }
+        if self.server.hosts.is_empty() {
+            problems.push("`server.hosts` must contain at least one address".to_string());
+        }
+        if self.server.workers == Some(0) {
+            problems.push("`server.workers` must be greater than 0".to_string());
+        }
+        if let Some(tls) = &self.server.tls {
+            if !tls.cert_path.is_file() {
+                problems.push(format!(
+                    "`server.tls.cert_path` {:?} doesn't exist",
+                    tls.cert_path
+                ));
+            }
+            if !tls.key_path.is_file() {
+                problems.push(format!(
+                    "`server.tls.key_path` {:?} doesn't exist",
+                    tls.key_path
+                ));
+            }
+            if tls.redirect_port == Some(self.application_port) {
+                problems.push(
+                    "`server.tls.redirect_port` must differ from `application_port`".to_string(),
+                );
+            }
+        }
+        if self.cors.allow_credentials && self.cors.allowed_origins.iter().any(|o| o == "*") {
+            problems.push(
+                "`cors.allow_credentials` can't be combined with `*` in `cors.allowed_origins`"
+                    .to_string(),
+            );
+        }
+        for origin in &self.cors.allowed_origins {
+            if origin != "*" && (!origin.contains("://") || origin.ends_with('/')) {
+                problems.push(format!(
+                    "`cors.allowed_origins` contains {:?}, which isn't an origin like `https://example.com`",
+                    origin
+                ));
+            }
+        }
+        for method in &self.cors.allowed_methods {
+            if Method::from_bytes(method.as_bytes()).is_err() {
+                problems.push(format!(
+                    "`cors.allowed_methods` contains {:?}, which isn't an HTTP method",
+                    method
+                ));
+            }
+        }
+        for header in &self.cors.allowed_headers {
+            if HeaderName::from_bytes(header.as_bytes()).is_err() {
+                problems.push(format!(
+                    "`cors.allowed_headers` contains {:?}, which isn't a header name",
+                    header
+                ));
+            }
+        }
+        if let Some(admin_port) = self.metrics.admin_port {
+            let redirect_port = self.server.tls.as_ref().and_then(|tls| tls.redirect_port);
+            if admin_port == 0 {
+                problems.push("`metrics.admin_port` must be between 1 and 65535".to_string());
+            } else if admin_port == self.application_port || Some(admin_port) == redirect_port {
+                problems.push(
+                    "`metrics.admin_port` must differ from the other ports of the server"
+                        .to_string(),
+                );
+            }
+        }
+        if self.database.port == 0 {
+            problems.push("`database.port` must be between 1 and 65535".to_string());
+        }
+        if self.database.max_connections == 0 {
+            problems.push("`database.max_connections` must be greater than 0".to_string());
+        }
+        if self.database.min_connections > self.database.max_connections {
+            problems.push(
+                "`database.min_connections` must not exceed `database.max_connections`".to_string(),
+            );
+        }
+        if self.sessions.backend == SessionBackend::Redis && self.sessions.redis_url.is_none() {
+            problems.push("`sessions.redis_url` is required by the `redis` backend".to_string());
+        }
+        if self.sessions.purge_interval_seconds == 0 {
+            problems.push("`sessions.purge_interval_seconds` must be greater than 0".to_string());
+        }
+        if self.sessions.refresh_threshold_seconds == 0 {
+            problems.push(
+                "`sessions.refresh_threshold_seconds` must be greater than 0, or sessions would \
+                 never be extended"
+                    .to_string(),
+            );
+        }
+        let session_secret = self.session_secret.expose();
+        let distinct_bytes = session_secret.iter().collect::<HashSet<_>>().len();
+        if session_secret.len() < MIN_SESSION_SECRET_LENGTH {
+            problems.push(format!(
+                "`session_secret` must be at least {} bytes long, but is {}",
+                MIN_SESSION_SECRET_LENGTH,
+                session_secret.len()
+            ));
+        } else if distinct_bytes < session_secret.len() / 2 {
+            problems.push(
+                "`session_secret` has too little entropy, use a randomly generated one".to_string(),
+            );
+        }
+        if self.log.days_to_keep == 0 {
+            problems.push("`log.days_to_keep` must be greater than 0".to_string());
+        }
+        if self.log.max_file_size == Some(0) {
+            problems.push("`log.max_file_size` must be greater than 0".to_string());
+        }
+        if let Err(error) = check_writable(Path::new(&self.log.path_string)) {
+            problems.push(format!(
+                "`log.path` {:?} is not writable: {}",
+                self.log.path_string, error
+            ));
+        }
+        if cfg!(not(feature = "embed-client"))
+            && !self.static_files.public_dir.join("index.html").is_file()
+        {
+            problems.push(format!(
+                "`static_files.public_dir` {:?} doesn't contain an `index.html`",
+                self.static_files.public_dir
+            ));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+fn check_writable(dir: &Path) -> std::io::Result<()> {
+    create_dir_all(dir)?;
+    let probe = dir.join(".write-check");
+    File::create(&probe)?;
+    remove_file(probe)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ServerSettings {
+    /// Addresses to listen on, e.g. `0.0.0.0` to be reachable from a container network.
+    pub hosts: Vec<String>,
+    /// Number of worker threads, defaults to the number of physical CPU cores.
+    #[serde(default)]
+    pub workers: Option<usize>,
+    pub keep_alive_seconds: u64,
+    /// Time between failing readiness and closing the listeners on SIGTERM or SIGINT, so load
+    /// balancers stop sending new requests first.
+    #[serde(default)]
+    pub shutdown_delay_seconds: u64,
+    /// Time workers get to finish in-flight requests when shutting down, and background tasks
+    /// get to stop afterwards.
+    pub shutdown_timeout_seconds: u64,
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TlsSettings {
+    /// PEM encoded certificate chain.
+    pub cert_path: PathBuf,
+    /// PEM encoded private key.
+    pub key_path: PathBuf,
+    /// Plain HTTP port redirecting to `application_port`.
+    #[serde(default)]
+    pub redirect_port: Option<u16>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct StaticSettings {
+    /// Directory the built client is served from, unless built with the `embed-client`
+    /// feature.
+    pub public_dir: PathBuf,
+    /// `Cache-Control` per asset directory (`js`, `css`, `img`, `lang`), with `index.html` and
+    /// `favicon.ico` keyed by their file name. Files with a content hash in their name are
+    /// always cached as immutable.
+    #[serde(default)]
+    pub cache_control: HashMap<String, String>,
+    /// Serve `.br` or `.gz` siblings of files to clients accepting them.
+    #[serde(default)]
+    pub precompressed: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SecurityHeadersSettings {
+    pub enabled: bool,
+    /// `{nonce}` is replaced by a nonce generated per request, which is also added to the
+    /// `<script>` tags of `index.html`.
+    pub content_security_policy: String,
+    pub referrer_policy: String,
+    /// `X-Frame-Options`, for browsers not supporting `frame-ancestors` of the CSP.
+    pub frame_options: String,
+    pub permissions_policy: String,
+    /// `Strict-Transport-Security`, only sent if TLS is configured.
+    pub hsts: String,
+    /// Headers per path prefix replacing the ones above, an empty value removes the header.
+    #[serde(default)]
+    pub overrides: HashMap<String, HashMap<String, String>>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct CorsSettings {
+    /// Origins like `http://localhost:8000` allowed to call the API, `*` allows any origin.
+    /// CORS handling is disabled if empty, so only same-origin requests work.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub allow_credentials: bool,
+    #[serde(default)]
+    pub max_age_seconds: Option<usize>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct MetricsSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Serve `/metrics` on this port only, so that it can be kept off the public network.
+    /// Otherwise it is served on `application_port`.
+    #[serde(default)]
+    pub admin_port: Option<u16>,
+    /// Address the admin port is bound to instead of `server.hosts`.
+    #[serde(default = "default_admin_host")]
+    pub admin_host: String,
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        MetricsSettings {
+            enabled: false,
+            admin_port: None,
+            admin_host: default_admin_host(),
+        }
+    }
+}
+
+fn default_admin_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct SessionSettings {
+    /// `postgres`, `memory` for tests and single instances, or `redis`.
+    #[serde(default)]
+    pub backend: SessionBackend,
+    /// Like `redis://:password@localhost:6379/0`.
+    #[serde(default)]
+    pub redis_url: Option<Secret<String>>,
+    /// Interval of deleting expired sessions from backends not expiring them by themselves.
+    #[serde(default = "default_purge_interval_seconds")]
+    pub purge_interval_seconds: u64,
+    /// Requests only extend a session in the backend once it has less than this left, so that
+    /// a burst of requests doesn't write each time. Use the session lifetime of 1800 to extend
+    /// it on every request.
+    #[serde(default = "default_refresh_threshold_seconds")]
+    pub refresh_threshold_seconds: u64,
+    /// Time validated sessions are served from memory without asking the backend, 0 disables
+    /// the cache. Sessions deleted by another instance stay valid here for this long.
+    #[serde(default = "default_cache_ttl_seconds")]
+    pub cache_ttl_seconds: u64,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        SessionSettings {
+            backend: SessionBackend::default(),
+            redis_url: None,
+            purge_interval_seconds: default_purge_interval_seconds(),
+            refresh_threshold_seconds: default_refresh_threshold_seconds(),
+            cache_ttl_seconds: default_cache_ttl_seconds(),
+        }
+    }
+}
+
+fn default_purge_interval_seconds() -> u64 {
+    60
+}
+
+fn default_refresh_threshold_seconds() -> u64 {
+    1500
+}
+
+fn default_cache_ttl_seconds() -> u64 {
+    5
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Debug)]
 pub struct DatabaseSettings {
     pub username: String,
-    pub password: String,
+    pub password: Secret<String>,
     pub host: String,
     pub port: u16,
     pub database_name: String,
+    /// Whether to apply, only verify or ignore pending migrations at startup.
+    #[serde(default)]
+    pub migrations: MigrationMode,
+    /// `disable`, `allow`, `prefer`, `require`, `verify-ca` or `verify-full`.
+    #[serde(default = "default_ssl_mode", deserialize_with = "string_to_ssl_mode")]
+    pub ssl_mode: PgSslMode,
+    /// Shown in `pg_stat_activity`.
+    #[serde(default = "default_application_name")]
+    pub application_name: String,
+    #[serde(default = "default_max_connections")]
+    pub max_connections: u32,
+    #[serde(default)]
+    pub min_connections: u32,
+    /// Time a request waits for a free connection before failing.
+    #[serde(default = "default_acquire_timeout_seconds")]
+    pub acquire_timeout_seconds: u64,
+    /// Close connections unused for this long, down to `min_connections`.
+    #[serde(default)]
+    pub idle_timeout_seconds: Option<u64>,
+    /// Cancel statements running longer than this.
+    #[serde(default)]
+    pub statement_timeout_ms: Option<u64>,
+    /// Keep retrying to connect at startup for this long, e.g. while Postgres is starting next
+    /// to the server. `0` gives up after the first attempt.
+    #[serde(default)]
+    pub connect_retry_seconds: u64,
 }
 
 impl DatabaseSettings {
-    pub fn connection_string(&self) -> String {
-        format!(
-            "postgres://{}:{}@{}:{}/{}",
-            self.username, self.password, self.host, self.port, self.database_name
-        )
+    pub fn connect_options(&self) -> PgConnectOptions {
+        let options = PgConnectOptions::new()
+            .host(&self.host)
+            .port(self.port)
+            .username(&self.username)
+            .password(self.password.expose())
+            .database(&self.database_name)
+            .ssl_mode(self.ssl_mode)
+            .application_name(&self.application_name);
+        match self.statement_timeout_ms {
+            Some(timeout) => options.options([("statement_timeout", format!("{}ms", timeout))]),
+            None => options,
+        }
+    }
+
+    pub fn pool_options(&self) -> PgPoolOptions {
+        PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(Duration::from_secs(self.acquire_timeout_seconds))
+            .idle_timeout(self.idle_timeout_seconds.map(Duration::from_secs))
     }
 }
 
-#[derive(Deserialize)]
+fn default_ssl_mode() -> PgSslMode {
+    PgSslMode::Prefer
+}
+
+fn default_application_name() -> String {
+    env!("CARGO_PKG_NAME").to_string()
+}
+
+fn default_max_connections() -> u32 {
+    10
+}
+
+fn default_acquire_timeout_seconds() -> u64 {
+    30
+}
+
+#[derive(Deserialize, Clone, Debug)]
 pub struct LogSettings {
     #[serde(deserialize_with = "string_to_level_filter")]
     pub max_level: log::Level,
+    /// Per-module levels as `env_logger`-style directives, e.g. `sqlx=warn,actix_web=info`.
+    /// Can be overridden by the `RUST_LOG` environment variable.
+    #[serde(default)]
+    pub filter: Option<String>,
     #[serde(rename = "path")]
     pub path_string: String,
     pub days_to_keep: u64,
+    /// Start a new file for the day once the current one would exceed this many bytes.
+    #[serde(default)]
+    pub max_file_size: Option<u64>,
+    /// Gzip log files after rolling over.
+    #[serde(default)]
+    pub compress: bool,
+    #[serde(default)]
+    pub access: AccessLogSettings,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct AccessLogSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Write to `access-YYYY-MM-DD.txt` files instead of the main log.
+    #[serde(default = "default_true")]
+    pub separate_file: bool,
+    /// Path prefixes of requests not to log, e.g. `/js/` for static assets.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl Default for AccessLogSettings {
+    fn default() -> Self {
+        AccessLogSettings {
+            enabled: false,
+            separate_file: true,
+            exclude: Vec::new(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A value that must not end up in logs, so its `Debug` output is redacted.
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> Debug for Secret<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+fn string_to_secret_bytes<'de, D>(deserializer: D) -> Result<Secret<Vec<u8>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let string: String = Deserialize::deserialize(deserializer)?;
+    let bytes = match string.trim().strip_prefix("base64:") {
+        Some(base64) => general_purpose::STANDARD
+            .decode(base64)
+            .map_err(|_| Error::custom("invalid base64 encoding"))?,
+        None => hex::decode(string.trim()).map_err(|_| Error::custom("invalid hex encoding"))?,
+    };
+    Ok(Secret(bytes))
+}
+
+fn string_to_ssl_mode<'de, D>(deserializer: D) -> Result<PgSslMode, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let string: String = Deserialize::deserialize(deserializer)?;
+    PgSslMode::from_str(string.as_str()).map_err(|e| Error::custom(e.to_string()))
 }
 
 fn string_to_level_filter<'de, D>(deserializer: D) -> Result<log::Level, D::Error>
@@ -48,16 +514,182 @@ where
     }
 }
 
-pub fn get_configuration() -> Result<Settings, config::ConfigError> {
-    println!(
-        "{:?}",
-        std::env::current_dir().expect("Couldn't get present working directory.")
-    );
-    let settings = config::Config::builder()
-        .add_source(config::File::new(
-            "config/configuration.json",
-            config::FileFormat::Json,
-        ))
-        .build()?;
-    settings.try_deserialize::<Settings>()
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    Local,
+    Test,
+    Production,
+}
+
+impl Profile {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Profile::Local => "local",
+            Profile::Test => "test",
+            Profile::Production => "production",
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "local" => Ok(Profile::Local),
+            "test" => Ok(Profile::Test),
+            "production" => Ok(Profile::Production),
+            other => Err(anyhow!(
+                "Unknown profile `{}`, use `local`, `test` or `production`",
+                other
+            )),
+        }
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Reads the settings from these layers, each one overriding the ones before:
+/// - `config/base.json`
+/// - `config/<profile>.json`, where the profile is taken from `APP_PROFILE` (default `local`)
+/// - the file given by `--config`, if any
+/// - environment variables like `APP_DATABASE__PASSWORD` for `database.password`
+///
+/// Secrets may be given as `session_secret_file`, `database.password_file` or
+/// `sessions.redis_url_file` instead, naming a file to read them from (e.g. a Docker or Kubernetes
+/// secret).
+pub fn get_configuration(config_file: Option<&Path>) -> anyhow::Result<Settings> {
+    let profile: Profile = match std::env::var(PROFILE_VARIABLE) {
+        Ok(profile) => profile
+            .parse()
+            .with_context(|| format!("Invalid value of {}", PROFILE_VARIABLE))?,
+        Err(_) => Profile::Local,
+    };
+    load_configuration(profile, config_file)
+}
+
+/// Like `get_configuration`, but for the given profile instead of the one in `APP_PROFILE`.
+pub fn load_configuration(
+    profile: Profile,
+    config_file: Option<&Path>,
+) -> anyhow::Result<Settings> {
+    load_configuration_from(profile, config_file, std::env::vars().collect())
+}
+
+/// Like `load_configuration`, but reads the `APP_` variables from `environment` instead of the
+/// process environment.
+fn load_configuration_from(
+    profile: Profile,
+    config_file: Option<&Path>,
+    environment: HashMap<String, String>,
+) -> anyhow::Result<Settings> {
+    let config_dir = Path::new(CONFIG_DIR);
+    let mut builder = config::Config::builder()
+        .add_source(
+            config::File::from(config_dir.join("base.json")).format(config::FileFormat::Json),
+        )
+        .add_source(
+            config::File::from(config_dir.join(format!("{}.json", profile)))
+                .format(config::FileFormat::Json)
+                .required(false),
+        );
+    if let Some(config_file) = config_file {
+        builder = builder.add_source(config::File::from(config_file));
+    }
+    // values stay strings, converted by the settings they end up in, so that passwords and
+    // secrets like `0123` aren't parsed as numbers
+    builder = builder
+        .add_source(
+            config::Environment::with_prefix("APP")
+                .prefix_separator("_")
+                .separator("__")
+                .source(Some(environment.clone())),
+        )
+        .set_override("profile", profile.as_str())?;
+    // `config` only splits lists when parsing all values
+    for key in LIST_KEYS {
+        let variable = format!("APP_{}", key.replace('.', "__").to_uppercase());
+        if let Some(value) = environment.get(&variable) {
+            let values: Vec<String> = value.split(',').map(str::to_string).collect();
+            builder = builder.set_override(key, values)?;
+        }
+    }
+
+    let config = builder
+        .clone()
+        .build()
+        .context("Couldn't read configuration")?;
+    for key in SECRET_KEYS {
+        let file_key = format!("{}_file", key);
+        if let Ok(path) = config.get_string(&file_key) {
+            let secret = std::fs::read_to_string(&path)
+                .with_context(|| format!("Couldn't read `{}` from {:?}", file_key, path))?;
+            builder = builder.set_override(key, secret.trim_end())?;
+        }
+    }
+    let config = builder.build().context("Couldn't read configuration")?;
+
+    let settings = serde_path_to_error::deserialize::<_, Settings>(config).map_err(|error| {
+        anyhow!(
+            "Invalid configuration at `{}`: {}",
+            error.path(),
+            error.inner()
+        )
+    })?;
+    if settings.profile == Profile::Production
+        && hex::encode(settings.session_secret.expose()) == SAMPLE_SESSION_SECRET
+    {
+        return Err(anyhow!(
+            "Invalid configuration at `session_secret`: the sample secret must not be used in production"
+        ));
+    }
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environment_values_are_not_parsed_as_numbers() {
+        let environment = HashMap::from([
+            ("APP_DATABASE__PASSWORD".to_string(), "0123".to_string()),
+            (
+                "APP_SESSION_SECRET".to_string(),
+                "00112233445566778899001122334455".to_string(),
+            ),
+            ("APP_SERVER__HOSTS".to_string(), "127.0.0.1,::1".to_string()),
+        ]);
+
+        let settings = load_configuration_from(Profile::Test, None, environment).unwrap();
+
+        assert_eq!(settings.database.password.expose(), "0123");
+        assert_eq!(
+            hex::encode(settings.session_secret.expose()),
+            "00112233445566778899001122334455"
+        );
+        assert_eq!(settings.server.hosts, ["127.0.0.1", "::1"]);
+    }
+
+    #[test]
+    fn invalid_cors_methods_and_headers_are_reported() {
+        let mut settings = load_configuration_from(Profile::Test, None, HashMap::new()).unwrap();
+        settings.cors.allowed_methods = vec!["GET".to_string(), "NOT A METHOD".to_string()];
+        settings.cors.allowed_headers = vec!["content-type".to_string(), "x:y".to_string()];
+
+        let problems = settings.validate().unwrap_err();
+
+        assert!(problems.contains(
+            &"`cors.allowed_methods` contains \"NOT A METHOD\", which isn't an HTTP method"
+                .to_string()
+        ));
+        assert!(problems.contains(
+            &"`cors.allowed_headers` contains \"x:y\", which isn't a header name".to_string()
+        ));
+    }
 }
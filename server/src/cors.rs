@@ -0,0 +1,23 @@
+use actix_cors::Cors;
+
+use crate::configuration::CorsSettings;
+
+/// Builds the CORS middleware for the `/api` scope. It has to wrap `Authorisation`, so that
+/// preflight requests are answered before they could be rejected as unauthorised.
+pub fn cors(settings: &CorsSettings) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(settings.allowed_methods.iter().map(String::as_str))
+        .allowed_headers(settings.allowed_headers.iter().map(String::as_str))
+        .expose_headers(["x-request-id"])
+        .max_age(settings.max_age_seconds);
+    for origin in &settings.allowed_origins {
+        cors = match origin.as_str() {
+            "*" => cors.allow_any_origin(),
+            origin => cors.allowed_origin(origin),
+        };
+    }
+    if settings.allow_credentials {
+        cors = cors.supports_credentials();
+    }
+    cors
+}
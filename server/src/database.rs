@@ -0,0 +1,207 @@
+use actix_web::rt::time::{sleep, timeout};
+use anyhow::{anyhow, Context};
+use log::{log, Level};
+use serde::Deserialize;
+use sqlx::migrate::Migrator;
+use sqlx::{Connection, PgConnection, PgPool};
+use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+use crate::configuration::DatabaseSettings;
+
+/// How long a single attempt to connect may take, e.g. while packets to the database are
+/// dropped.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5);
+/// SQLSTATE of "the database system is starting up".
+const CANNOT_CONNECT_NOW: &str = "57P03";
+
+/// The migrations of `migrations/`, embedded at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// What to do at startup if the database schema doesn't match the migrations of the binary.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MigrationMode {
+    /// Apply pending migrations.
+    #[default]
+    Apply,
+    /// Refuse to start if there are pending migrations.
+    Verify,
+    /// Don't check the schema.
+    Off,
+}
+
+/// How the migrations applied to the database relate to the ones of the binary.
+#[derive(Debug, PartialEq)]
+pub enum SchemaStatus {
+    UpToDate,
+    /// Migrations of the binary not applied yet.
+    Behind(Vec<i64>),
+    /// Applied migrations the binary doesn't know, so it is older than the schema.
+    Ahead(Vec<i64>),
+}
+
+impl Display for SchemaStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaStatus::UpToDate => write!(f, "Database schema is up to date"),
+            SchemaStatus::Behind(pending) => write!(
+                f,
+                "Database schema is behind the binary, pending migrations: {:?}",
+                pending
+            ),
+            SchemaStatus::Ahead(unknown) => write!(
+                f,
+                "Database schema is ahead of the binary, unknown migrations: {:?}",
+                unknown
+            ),
+        }
+    }
+}
+
+/// Creates the pool if the database accepts a connection. Fails after one attempt, as the
+/// pool itself would keep retrying for `acquire_timeout_seconds`.
+pub async fn connect(settings: &DatabaseSettings) -> Result<PgPool, sqlx::Error> {
+    let connection = timeout(
+        CONNECT_TIMEOUT,
+        PgConnection::connect_with(&settings.connect_options()),
+    )
+    .await
+    .map_err(|_| sqlx::Error::Io(ErrorKind::TimedOut.into()))??;
+    connection.close().await?;
+    settings
+        .pool_options()
+        .connect_with(settings.connect_options())
+        .await
+}
+
+/// Connects like `connect`, but retries with exponential back-off for up to
+/// `connect_retry_seconds` while the database is unreachable or still starting.
+pub async fn connect_with_retry(settings: &DatabaseSettings) -> Result<PgPool, sqlx::Error> {
+    let deadline = Instant::now() + Duration::from_secs(settings.connect_retry_seconds);
+    let mut delay = INITIAL_RETRY_DELAY;
+    loop {
+        match connect(settings).await {
+            Ok(db_pool) => return Ok(db_pool),
+            Err(error) if is_transient(&error) && Instant::now() + delay < deadline => {
+                log!(
+                    Level::Warn,
+                    "Error: {}, while connecting to database, retrying in {:?}",
+                    error,
+                    delay
+                );
+                sleep(delay).await;
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Errors that may go away by waiting, unlike e.g. wrong credentials.
+fn is_transient(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(error) => error.code().as_deref() == Some(CANNOT_CONNECT_NOW),
+        _ => false,
+    }
+}
+
+/// Compares the successfully applied migrations with the ones embedded in the binary.
+pub async fn schema_status(db_pool: &PgPool) -> Result<SchemaStatus, sqlx::Error> {
+    let table: Option<String> = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations')::text")
+        .fetch_one(db_pool)
+        .await?;
+    let applied: Vec<i64> = match table {
+        Some(_) => {
+            sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+                .fetch_all(db_pool)
+                .await?
+        }
+        None => Vec::new(),
+    };
+    let unknown: Vec<i64> = applied
+        .iter()
+        .copied()
+        .filter(|version| {
+            MIGRATOR
+                .iter()
+                .all(|migration| migration.version != *version)
+        })
+        .collect();
+    if !unknown.is_empty() {
+        return Ok(SchemaStatus::Ahead(unknown));
+    }
+    let pending: Vec<i64> = MIGRATOR
+        .iter()
+        .map(|migration| migration.version)
+        .filter(|version| !applied.contains(version))
+        .collect();
+    if pending.is_empty() {
+        Ok(SchemaStatus::UpToDate)
+    } else {
+        Ok(SchemaStatus::Behind(pending))
+    }
+}
+
+/// Applies the pending migrations, refusing to touch a schema that is ahead of the binary.
+/// Returns the number of migrations applied.
+pub async fn migrate(db_pool: &PgPool) -> anyhow::Result<usize> {
+    match schema_status(db_pool)
+        .await
+        .context("Couldn't read the applied migrations")?
+    {
+        SchemaStatus::UpToDate => Ok(0),
+        SchemaStatus::Ahead(unknown) => Err(anyhow!(SchemaStatus::Ahead(unknown))),
+        SchemaStatus::Behind(pending) => {
+            MIGRATOR
+                .run(db_pool)
+                .await
+                .context("Couldn't apply the migrations")?;
+            Ok(pending.len())
+        }
+    }
+}
+
+/// Brings the schema in line with the binary according to `mode` at startup.
+pub async fn prepare_schema(db_pool: &PgPool, mode: MigrationMode) -> anyhow::Result<()> {
+    match mode {
+        MigrationMode::Apply => migrate(db_pool).await.map(|_| ()),
+        MigrationMode::Verify => match schema_status(db_pool)
+            .await
+            .context("Couldn't read the applied migrations")?
+        {
+            SchemaStatus::UpToDate => Ok(()),
+            status => Err(anyhow!(status)),
+        },
+        MigrationMode::Off => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::{load_configuration, Profile};
+    use std::net::TcpListener;
+
+    #[actix_web::test]
+    async fn refused_connection_fails_without_waiting_for_the_pool() {
+        let mut settings = load_configuration(Profile::Test, None).unwrap().database;
+        settings.port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        settings.host = "127.0.0.1".to_string();
+        settings.connect_retry_seconds = 0;
+        let start = Instant::now();
+
+        let error = connect_with_retry(&settings).await.unwrap_err();
+
+        assert!(matches!(error, sqlx::Error::Io(_)), "{:?}", error);
+        assert!(start.elapsed() < Duration::from_secs(settings.acquire_timeout_seconds));
+    }
+}
@@ -0,0 +1,18 @@
+pub mod access_log;
+pub mod admin;
+pub mod api_error;
+pub mod authorisation;
+pub mod cli;
+pub mod clock;
+pub mod configuration;
+pub mod cors;
+pub mod database;
+pub mod logging;
+pub mod metrics;
+pub mod repository;
+pub mod routes;
+pub mod security_headers;
+pub mod shutdown;
+pub mod startup;
+pub mod tls;
+pub mod validation;
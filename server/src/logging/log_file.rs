@@ -0,0 +1,204 @@
+use crate::configuration::LogSettings;
+use chrono::{Days, NaiveDate};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{create_dir_all, read_dir, remove_file, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The log file currently written to, named `<prefix>-YYYY-MM-DD.txt` for the first file of a
+/// day and `<prefix>-YYYY-MM-DD.N.txt` for further files created when `max_file_size` is
+/// exceeded.
+///
+/// I/O errors never panic: they are reported on stderr and the lines that couldn't be written
+/// are printed there instead. Opening a file is retried on the next roll over.
+pub struct LogFile {
+    dir: PathBuf,
+    prefix: &'static str,
+    days_to_keep: u64,
+    max_file_size: Option<u64>,
+    compress: bool,
+    date: NaiveDate,
+    index: u32,
+    size: u64,
+    writer: Option<BufWriter<File>>,
+}
+
+impl LogFile {
+    pub fn open(settings: &LogSettings, prefix: &'static str, date: NaiveDate) -> Self {
+        let dir = PathBuf::from(&settings.path_string);
+        if let Err(error) = create_dir_all(&dir) {
+            eprintln!("Error: {}, while creating log directory {:?}", error, dir);
+        }
+        // continue with the first file of the day that is neither rotated nor full
+        let mut index = 0;
+        loop {
+            let path = file_path(&dir, prefix, date, index);
+            let rotated = path.with_extension("txt.gz").exists();
+            let full = match (settings.max_file_size, path.metadata()) {
+                (Some(max_file_size), Ok(metadata)) => metadata.len() >= max_file_size,
+                _ => false,
+            };
+            if rotated || full {
+                index += 1;
+            } else {
+                break;
+            }
+        }
+        let mut log_file = LogFile {
+            dir,
+            prefix,
+            days_to_keep: settings.days_to_keep,
+            max_file_size: settings.max_file_size,
+            compress: settings.compress,
+            date,
+            index,
+            size: 0,
+            writer: None,
+        };
+        log_file.open_writer();
+        log_file
+    }
+
+    fn path(&self) -> PathBuf {
+        file_path(&self.dir, self.prefix, self.date, self.index)
+    }
+
+    fn open_writer(&mut self) {
+        let path = self.path();
+        match File::options().append(true).create(true).open(&path) {
+            Ok(file) => {
+                self.size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+                self.writer = Some(BufWriter::new(file));
+            }
+            Err(error) => {
+                eprintln!("Error: {}, while opening log file {:?}", error, path);
+                self.size = 0;
+                self.writer = None;
+            }
+        }
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    pub fn roll_over_if_needed(&mut self, today: NaiveDate, next_line_size: u64) {
+        if today != self.date {
+            self.roll_over(today, 0);
+            self.prune(today);
+        } else if self.max_file_size.is_some_and(|max_file_size| {
+            self.size > 0 && self.size + next_line_size > max_file_size
+        }) {
+            self.roll_over(today, self.index + 1);
+        } else if self.writer.is_none() {
+            self.open_writer();
+        }
+    }
+
+    fn roll_over(&mut self, date: NaiveDate, index: u32) {
+        self.flush();
+        let rotated_path = self.path();
+        let was_open = self.writer.take().is_some();
+        self.date = date;
+        self.index = index;
+        self.open_writer();
+        if self.compress && was_open {
+            // compressing might take a while, so don't hold up the lines still to be written
+            std::thread::spawn(move || {
+                if let Err(error) = gzip(&rotated_path) {
+                    eprintln!("Error: {}, while compressing {:?}", error, rotated_path);
+                }
+            });
+        }
+    }
+
+    pub fn write_line(&mut self, line: &str) {
+        let Some(writer) = self.writer.as_mut() else {
+            eprintln!("{}", line);
+            return;
+        };
+        match writeln!(writer, "{}", line) {
+            Ok(()) => self.size += line.len() as u64 + 1,
+            Err(error) => {
+                eprintln!(
+                    "Error: {}, while writing to log file {:?}",
+                    error,
+                    self.path()
+                );
+                eprintln!("{}", line);
+                self.writer = None;
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(error) = writer.flush() {
+                eprintln!(
+                    "Error: {}, while flushing log file {:?}",
+                    error,
+                    self.path()
+                );
+                self.writer = None;
+            }
+        }
+    }
+
+    /// Deletes log files older than `days_to_keep` days. The date is taken from the file name,
+    /// as the creation time isn't available on every file system.
+    pub fn prune(&self, today: NaiveDate) {
+        let Some(oldest_to_keep) = today.checked_sub_days(Days::new(self.days_to_keep)) else {
+            return;
+        };
+        let entries = match read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(error) => {
+                eprintln!(
+                    "Error: {}, while reading log directory {:?}",
+                    error, self.dir
+                );
+                return;
+            }
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                log_file_date(&entry.file_name().to_string_lossy(), self.prefix)
+                    .is_some_and(|date| date <= oldest_to_keep)
+            })
+            .for_each(|entry| {
+                if let Err(error) = remove_file(entry.path()) {
+                    eprintln!("Error: {}, while deleting {:?}", error, entry.path());
+                }
+            });
+    }
+}
+
+fn file_path(dir: &Path, prefix: &str, date: NaiveDate, index: u32) -> PathBuf {
+    let date_string = date.format("%Y-%m-%d");
+    let file_name = match index {
+        0 => format!("{}-{}.txt", prefix, date_string),
+        _ => format!("{}-{}.{}.txt", prefix, date_string, index),
+    };
+    dir.join(file_name)
+}
+
+fn log_file_date(file_name: &str, prefix: &str) -> Option<NaiveDate> {
+    let date_string = file_name
+        .strip_prefix(prefix)?
+        .strip_prefix('-')?
+        .get(..10)?;
+    NaiveDate::parse_from_str(date_string, "%Y-%m-%d").ok()
+}
+
+fn gzip(path: &Path) -> std::io::Result<()> {
+    let mut file = File::open(path)?;
+    let mut encoder = GzEncoder::new(
+        File::create(path.with_extension("txt.gz"))?,
+        Compression::default(),
+    );
+    std::io::copy(&mut file, &mut encoder)?;
+    encoder.finish()?;
+    remove_file(path)
+}
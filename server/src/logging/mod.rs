@@ -0,0 +1,197 @@
+mod log_file;
+
+use crate::clock::Clock;
+use crate::configuration::LogSettings;
+use chrono::NaiveDate;
+use env_filter::{Builder, Filter};
+use log::{LevelFilter, Metadata, Record, SetLoggerError};
+use log_file::LogFile;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Number of log lines that may wait for the writer thread before new ones go to stderr.
+const CHANNEL_CAPACITY: usize = 4096;
+/// How long `flush` waits for the writer thread to catch up.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether the main log file could be written to the last time it was tried.
+static SINK_WRITABLE: AtomicBool = AtomicBool::new(false);
+
+/// Target of the records written by the `AccessLog` middleware.
+pub const ACCESS_TARGET: &str = "access";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Sink {
+    Main,
+    Access,
+}
+
+enum Message {
+    Line(Sink, NaiveDate, String),
+    Flush(SyncSender<()>),
+}
+
+/// Formats log records and hands them to a background thread doing the file I/O,
+/// so logging from async handlers never blocks on the disk.
+pub struct Logger {
+    filter: Filter,
+    access_log: Option<Sink>,
+    sender: SyncSender<Message>,
+    // dates the lines and decides which daily file they go to
+    clock: Arc<dyn Clock>,
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        if metadata.target() == ACCESS_TARGET {
+            self.access_log.is_some()
+        } else {
+            self.filter.enabled(metadata)
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        let sink = if record.target() == ACCESS_TARGET {
+            self.access_log
+        } else {
+            self.filter.matches(record).then_some(Sink::Main)
+        };
+        if let Some(sink) = sink {
+            let now = self.clock.now();
+            let line = format!(
+                "{} [{}]: {}",
+                now.format("%Y-%m-%d %H:%M:%S%.3f"),
+                record.level(),
+                record.args()
+            );
+            match self
+                .sender
+                .try_send(Message::Line(sink, now.date_naive(), line))
+            {
+                Ok(()) => (),
+                Err(TrySendError::Full(Message::Line(_, _, line)))
+                | Err(TrySendError::Disconnected(Message::Line(_, _, line))) => {
+                    eprintln!("{}", line)
+                }
+                Err(_) => (),
+            }
+        }
+    }
+
+    fn flush(&self) {
+        let (ack_sender, ack_receiver) = sync_channel(1);
+        if self.sender.send(Message::Flush(ack_sender)).is_ok() {
+            let _ = ack_receiver.recv_timeout(FLUSH_TIMEOUT);
+        }
+    }
+}
+
+impl Logger {
+    fn new(settings: LogSettings, clock: Arc<dyn Clock>) -> Box<Self> {
+        let today = clock.now().date_naive();
+        let log_file = LogFile::open(&settings, "log", today);
+        log_file.prune(today);
+        let access_log_file = (settings.access.enabled && settings.access.separate_file)
+            .then(|| LogFile::open(&settings, "access", today));
+        if let Some(access_log_file) = &access_log_file {
+            access_log_file.prune(today);
+        }
+        let access_log = match (settings.access.enabled, settings.access.separate_file) {
+            (false, _) => None,
+            (true, false) => Some(Sink::Main),
+            (true, true) => Some(Sink::Access),
+        };
+        let (sender, receiver) = sync_channel(CHANNEL_CAPACITY);
+        if let Err(error) = thread::Builder::new()
+            .name("logger".to_string())
+            .spawn(move || write_lines(receiver, log_file, access_log_file))
+        {
+            eprintln!("Error: {}, while starting logger thread", error);
+        }
+        Box::new(Logger {
+            filter: build_filter(&settings),
+            access_log,
+            sender,
+            clock,
+        })
+    }
+    pub fn init(config: LogSettings, clock: Arc<dyn Clock>) -> Result<(), SetLoggerError> {
+        let logger = Self::new(config, clock);
+        let max_level = match logger.access_log {
+            Some(_) => logger.filter.filter().max(LevelFilter::Info),
+            None => logger.filter.filter(),
+        };
+        log::set_boxed_logger(logger).map(|()| log::set_max_level(max_level))
+    }
+}
+
+/// Builds the filter from `max_level` as the default level, refined by the `env_logger`-style
+/// directives in `filter` (e.g. `sqlx=warn,actix_web=info`) and finally by `RUST_LOG`.
+fn build_filter(settings: &LogSettings) -> Filter {
+    let mut builder = Builder::new();
+    builder.filter_level(settings.max_level.to_level_filter());
+    if let Some(filter) = &settings.filter {
+        builder.parse(filter);
+    }
+    if let Ok(filter) = std::env::var("RUST_LOG") {
+        builder.parse(&filter);
+    }
+    builder.build()
+}
+
+struct LogFiles {
+    main: LogFile,
+    access: Option<LogFile>,
+}
+
+impl LogFiles {
+    fn flush(&mut self) {
+        self.main.flush();
+        if let Some(access) = self.access.as_mut() {
+            access.flush();
+        }
+    }
+}
+
+/// Tells whether log lines currently end up in the log file rather than on stderr.
+pub fn sink_writable() -> bool {
+    SINK_WRITABLE.load(Ordering::Relaxed)
+}
+
+fn write_lines(receiver: Receiver<Message>, main: LogFile, access: Option<LogFile>) {
+    let mut log_files = LogFiles { main, access };
+    SINK_WRITABLE.store(log_files.main.is_writable(), Ordering::Relaxed);
+    while let Ok(message) = receiver.recv() {
+        handle_message(message, &mut log_files);
+        // write whatever else is waiting before paying for a flush
+        while let Ok(message) = receiver.try_recv() {
+            handle_message(message, &mut log_files);
+        }
+        log_files.flush();
+        SINK_WRITABLE.store(log_files.main.is_writable(), Ordering::Relaxed);
+    }
+    log_files.flush();
+}
+
+fn handle_message(message: Message, log_files: &mut LogFiles) {
+    match message {
+        Message::Line(sink, date, line) => {
+            let log_file = match (sink, log_files.access.as_mut()) {
+                (Sink::Access, Some(access)) => access,
+                _ => {
+                    println!("{}", line);
+                    &mut log_files.main
+                }
+            };
+            log_file.roll_over_if_needed(date, line.len() as u64 + 1);
+            log_file.write_line(&line);
+        }
+        Message::Flush(ack_sender) => {
+            log_files.flush();
+            let _ = ack_sender.send(());
+        }
+    }
+}
@@ -1,75 +1,56 @@
-mod api_error;
-mod authorisation;
-mod configuration;
-mod logging;
-mod routes;
-mod validation;
-
-use crate::api_error::{ApiError, ApiErrorType};
-use crate::authorisation::Authorisation;
-use crate::configuration::get_configuration;
-use crate::logging::Logger;
-use crate::routes::ExpiresAt;
-use actix_files::Files;
-use actix_web::web::Data;
-use actix_web::{web, HttpMessage, HttpServer};
-use sqlx::{Pool, Postgres};
+use clap::Parser;
+use server::admin;
+use server::cli::{Cli, Command};
+use server::clock::SystemClock;
+use server::configuration::get_configuration;
+use server::logging::Logger;
+use server::startup::Application;
+use std::sync::Arc;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let configuration = get_configuration().expect("Couldn't read configuration file.");
-
-    Logger::init(configuration.log).expect("Couldn't initialize logger");
-    let session_secret = bytes::Bytes::from(configuration.session_secret);
-
-    let db_url = configuration.database.connection_string();
-    let db_pool = Pool::<Postgres>::connect(db_url.as_str())
-        .await
-        .expect("Couldn't connect to database.");
-
-    let json_parse_config = web::JsonConfig::default()
-        .limit(512)
-        .content_type(|mime| mime == "application/json")
-        .content_type_required(true)
-        .error_handler(|_, req| {
-            let api_error = ApiError::get_into(req)(ApiErrorType::BadRequest);
-            req.extensions_mut().insert(api_error.clone());
-            req.extensions_mut().insert::<ExpiresAt>(0);
-            api_error.error.into()
-        });
+    let cli = Cli::parse();
+    let config_file = cli.config.clone();
+    let command = cli.command();
+    if let Command::Secret(command) = &command {
+        admin::generate_secret(command);
+        return Ok(());
+    }
 
-    fn serve_static_dir(dir_string: &str) -> Files {
-        let mount_path = "/".to_owned() + dir_string;
-        let serve_from = "../public/".to_owned() + dir_string;
-        Files::new(&*mount_path, serve_from)
+    let configuration = match get_configuration(config_file.as_deref()) {
+        Ok(configuration) => configuration,
+        Err(error) => {
+            eprintln!("Error: {:#}", error);
+            std::process::exit(1);
+        }
+    };
+    if !matches!(command, Command::Serve | Command::CheckConfig) {
+        if let Err(error) = admin::run(command, &configuration, Arc::new(SystemClock)).await {
+            eprintln!("Error: {:#}", error);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Err(problems) = configuration.validate() {
+        for problem in problems {
+            eprintln!("Error: {}", problem);
+        }
+        std::process::exit(1);
+    }
+    if let Command::CheckConfig = command {
+        println!("Configuration is valid.");
+        return Ok(());
     }
 
-    HttpServer::new(move || {
-        actix_web::App::new()
-            .app_data(Data::new(db_pool.clone()))
-            .service(
-                web::scope("")
-                    .service(serve_static_dir("js"))
-                    .service(serve_static_dir("css"))
-                    .service(serve_static_dir("img"))
-                    .service(serve_static_dir("lang"))
-                    .service(
-                        web::scope("/api")
-                            .app_data(Data::new(session_secret.clone()))
-                            .app_data(json_parse_config.clone())
-                            .wrap(Authorisation)
-                            .route("/login", web::post().to(routes::login_handler))
-                            .route("/session", web::get().to(routes::session_handler))
-                            .route("/session", web::post().to(routes::set_user_language_handler))
-                            .route("/session", web::delete().to(routes::logout_handler))
-                            .route("/{route}", web::get().to(routes::not_found_handler)),
-                    )
-                    .route("/favicon.ico", web::get().to(routes::return_favicon))
-                    .route("/", web::get().to(routes::return_index))
-                    .route("/{route}", web::get().to(routes::return_index)),
-            )
-    })
-    .bind(("127.0.0.1", configuration.application_port))?
-    .run()
-    .await
+    Logger::init(configuration.log.clone(), Arc::new(SystemClock))
+        .expect("Couldn't initialize logger");
+    let application = match Application::build(configuration).await {
+        Ok(application) => application,
+        Err(error) => {
+            log::error!("Error: {:#}, while starting the server", error);
+            log::logger().flush();
+            std::process::exit(1);
+        }
+    };
+    application.run_until_stopped().await
 }
@@ -0,0 +1,256 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::rt::time::sleep;
+use actix_web::Error;
+use futures_util::future::LocalBoxFuture;
+use log::{log, Level};
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::api_error::ApiErrorType;
+use crate::clock::Clock;
+use crate::repository::SessionStore;
+use crate::shutdown::ShutdownSignal;
+
+/// Interval of counting the active sessions, so that scrapes don't each hit the `session`
+/// table.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(15);
+/// Route label of requests not matching any route, instead of their path, to keep the number
+/// of series bounded.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Registry with all metrics of the server, shared between the workers.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    login_attempts: IntCounterVec,
+    sessions_cleaned_up: IntCounter,
+    bcrypt_verify_duration: Histogram,
+    active_sessions: IntGauge,
+    db_pool_size: IntGauge,
+    db_pool_idle: IntGauge,
+    db_pool_acquire_duration: Histogram,
+}
+
+impl Metrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new();
+        let metrics = Metrics {
+            http_requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests handled"),
+                &["method", "route", "status", "error"],
+            )?,
+            http_request_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "http_request_duration_seconds",
+                    "Time taken to handle HTTP requests",
+                ),
+                &["method", "route", "error"],
+            )?,
+            login_attempts: IntCounterVec::new(
+                Opts::new("login_attempts_total", "Login attempts by result"),
+                &["result"],
+            )?,
+            sessions_cleaned_up: IntCounter::new(
+                "sessions_cleaned_up_total",
+                "Expired sessions deleted",
+            )?,
+            bcrypt_verify_duration: Histogram::with_opts(
+                HistogramOpts::new(
+                    "bcrypt_verify_duration_seconds",
+                    "Time taken to verify a password hash",
+                )
+                .buckets(vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5]),
+            )?,
+            active_sessions: IntGauge::new("active_sessions", "Sessions not yet expired")?,
+            db_pool_size: IntGauge::new("db_pool_connections", "Open database connections")?,
+            db_pool_idle: IntGauge::new(
+                "db_pool_idle_connections",
+                "Open database connections not in use",
+            )?,
+            db_pool_acquire_duration: Histogram::with_opts(
+                HistogramOpts::new(
+                    "db_pool_acquire_duration_seconds",
+                    "Time waited for a database connection, sampled periodically",
+                )
+                .buckets(vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0]),
+            )?,
+            registry,
+        };
+        let collectors: [Box<dyn Collector>; 9] = [
+            Box::new(metrics.http_requests.clone()),
+            Box::new(metrics.http_request_duration.clone()),
+            Box::new(metrics.login_attempts.clone()),
+            Box::new(metrics.sessions_cleaned_up.clone()),
+            Box::new(metrics.bcrypt_verify_duration.clone()),
+            Box::new(metrics.active_sessions.clone()),
+            Box::new(metrics.db_pool_size.clone()),
+            Box::new(metrics.db_pool_idle.clone()),
+            Box::new(metrics.db_pool_acquire_duration.clone()),
+        ];
+        for collector in collectors {
+            metrics.registry.register(collector)?;
+        }
+        Ok(metrics)
+    }
+
+    /// `result` is one of `success`, `failure` (invalid credentials) or `error`.
+    pub fn record_login(&self, result: &str) {
+        self.login_attempts.with_label_values(&[result]).inc();
+    }
+
+    pub fn observe_bcrypt_verify(&self, duration: Duration) {
+        self.bcrypt_verify_duration.observe(duration.as_secs_f64());
+    }
+
+    pub fn add_sessions_cleaned_up(&self, count: u64) {
+        self.sessions_cleaned_up.inc_by(count);
+    }
+
+    /// Updates the pool gauges and encodes all metrics in the Prometheus text format.
+    pub fn render(&self, db_pool: &PgPool) -> prometheus::Result<String> {
+        self.db_pool_size.set(db_pool.size() as i64);
+        self.db_pool_idle.set(db_pool.num_idle() as i64);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+
+    /// Background task refreshing the metrics read from the database and the session store
+    /// until shutdown.
+    pub async fn refresh(
+        self,
+        db_pool: PgPool,
+        sessions: Arc<dyn SessionStore>,
+        clock: Arc<dyn Clock>,
+        mut shutdown: ShutdownSignal,
+    ) {
+        loop {
+            self.refresh_from_database(&db_pool, sessions.as_ref(), clock.now())
+                .await;
+            tokio::select! {
+                _ = sleep(REFRESH_INTERVAL) => (),
+                _ = shutdown.recv() => return,
+            }
+        }
+    }
+
+    /// Samples the time to acquire a connection and counts the active sessions of the store.
+    /// Failures keep the previous values.
+    async fn refresh_from_database(
+        &self,
+        db_pool: &PgPool,
+        sessions: &dyn SessionStore,
+        now: DateTime<Utc>,
+    ) {
+        let start = Instant::now();
+        match db_pool.acquire().await {
+            Ok(_) => self
+                .db_pool_acquire_duration
+                .observe(start.elapsed().as_secs_f64()),
+            Err(error) => {
+                log!(
+                    Level::Error,
+                    "Error: {}, while acquiring connection for metrics",
+                    error
+                );
+            }
+        }
+        // errors are logged by the store
+        if let Ok(count) = sessions.count_active(now).await {
+            self.active_sessions.set(count as i64);
+        }
+    }
+}
+
+/// Counts requests and measures their duration by route, status and error of the response
+/// envelope.
+pub struct RequestMetrics {
+    metrics: Rc<Metrics>,
+}
+
+impl RequestMetrics {
+    pub fn new(metrics: Metrics) -> Self {
+        RequestMetrics {
+            metrics: Rc::new(metrics),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static + MessageBody,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsMiddleware {
+            service: service.into(),
+            metrics: self.metrics.clone(),
+        }))
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: Rc<S>,
+    metrics: Rc<Metrics>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static + MessageBody,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+        let metrics = self.metrics.clone();
+
+        Box::pin(async move {
+            let start = Instant::now();
+            let method = req.method().to_string();
+
+            let res = srv.call(req).await?;
+
+            let request = res.request();
+            let route = request
+                .match_pattern()
+                .unwrap_or(UNMATCHED_ROUTE.to_string());
+            let error = ApiErrorType::of_request(request).map_or("", |error| error.code());
+            let status = res.status().as_u16().to_string();
+            metrics
+                .http_requests
+                .with_label_values(&[&method, &route, &status, error])
+                .inc();
+            metrics
+                .http_request_duration
+                .with_label_values(&[&method, &route, error])
+                .observe(start.elapsed().as_secs_f64());
+            Ok(res)
+        })
+    }
+}
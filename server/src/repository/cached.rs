@@ -0,0 +1,226 @@
+use async_trait::async_trait;
+use chrono::Duration;
+use sqlx::types::chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use uuid::Uuid;
+
+use super::{RepositoryResult, Session, SessionStore};
+
+/// Puts a short-lived cache of recently validated sessions in front of another store, and only
+/// writes a refresh through once the remaining lifetime of a session drops below a threshold.
+///
+/// Sessions deleted by another instance or the `session` commands stay valid here for up to
+/// the cache TTL.
+pub struct CachedSessionStore {
+    inner: Arc<dyn SessionStore>,
+    cache_ttl: std::time::Duration,
+    refresh_threshold: Duration,
+    cache: Mutex<HashMap<Uuid, (Session, Instant)>>,
+}
+
+impl CachedSessionStore {
+    /// A `cache_ttl` of zero disables the cache, leaving only the refresh threshold.
+    pub fn new(
+        inner: Arc<dyn SessionStore>,
+        cache_ttl: std::time::Duration,
+        refresh_threshold: Duration,
+    ) -> Self {
+        CachedSessionStore {
+            inner,
+            cache_ttl,
+            refresh_threshold,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn cached(&self, session_id: Uuid) -> Option<Session> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(&session_id)
+            .filter(|(_, cached_at)| cached_at.elapsed() < self.cache_ttl)
+            .map(|(session, _)| session.clone())
+    }
+
+    fn remember(&self, session: &Session) {
+        if !self.cache_ttl.is_zero() {
+            let mut cache = self.cache.lock().unwrap();
+            cache.insert(session.id, (session.clone(), Instant::now()));
+        }
+    }
+
+    fn forget(&self, session_id: Uuid) {
+        self.cache.lock().unwrap().remove(&session_id);
+    }
+}
+
+#[async_trait]
+impl SessionStore for CachedSessionStore {
+    async fn create(&self, account_id: Uuid, now: DateTime<Utc>) -> RepositoryResult<Session> {
+        let session = self.inner.create(account_id, now).await?;
+        self.remember(&session);
+        Ok(session)
+    }
+
+    async fn find(&self, session_id: Uuid) -> RepositoryResult<Option<Session>> {
+        if let Some(session) = self.cached(session_id) {
+            return Ok(Some(session));
+        }
+        let found = self.inner.find(session_id).await?;
+        match &found {
+            Some(session) => self.remember(session),
+            None => self.forget(session_id),
+        }
+        Ok(found)
+    }
+
+    /// Returns the session unchanged while it has more than the threshold left.
+    async fn refresh(
+        &self,
+        session: &Session,
+        now: DateTime<Utc>,
+    ) -> RepositoryResult<Option<Session>> {
+        if session.expires_at - now > self.refresh_threshold {
+            return Ok(Some(session.clone()));
+        }
+        let refreshed = self.inner.refresh(session, now).await?;
+        match &refreshed {
+            Some(session) => self.remember(session),
+            None => self.forget(session.id),
+        }
+        Ok(refreshed)
+    }
+
+    async fn delete(&self, session_id: Uuid) -> RepositoryResult<()> {
+        self.inner.delete(session_id).await?;
+        self.forget(session_id);
+        Ok(())
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> RepositoryResult<u64> {
+        self.cache
+            .lock()
+            .unwrap()
+            .retain(|_, (_, cached_at)| cached_at.elapsed() < self.cache_ttl);
+        self.inner.purge_expired(now).await
+    }
+
+    async fn delete_for_account(&self, account_id: Uuid) -> RepositoryResult<u64> {
+        let deleted = self.inner.delete_for_account(account_id).await?;
+        self.cache
+            .lock()
+            .unwrap()
+            .retain(|_, (session, _)| session.account_id != account_id);
+        Ok(deleted)
+    }
+
+    async fn delete_all(&self) -> RepositoryResult<u64> {
+        let deleted = self.inner.delete_all().await?;
+        self.cache.lock().unwrap().clear();
+        Ok(deleted)
+    }
+
+    async fn list(&self) -> RepositoryResult<Vec<Session>> {
+        self.inner.list().await
+    }
+
+    async fn count_active(&self, now: DateTime<Utc>) -> RepositoryResult<u64> {
+        self.inner.count_active(now).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{session_lifetime, MemorySessionStore};
+
+    fn stores(cache_ttl: u64) -> (Arc<MemorySessionStore>, CachedSessionStore) {
+        let inner = Arc::new(MemorySessionStore::default());
+        let cached = CachedSessionStore::new(
+            inner.clone(),
+            std::time::Duration::from_secs(cache_ttl),
+            Duration::minutes(25),
+        );
+        (inner, cached)
+    }
+
+    fn session_expiring_in(now: DateTime<Utc>, expires_in: Duration) -> Session {
+        Session {
+            id: Uuid::new_v4(),
+            account_id: Uuid::new_v4(),
+            expires_at: now + expires_in,
+        }
+    }
+
+    #[actix_web::test]
+    async fn refresh_is_skipped_above_threshold() {
+        let (inner, cached) = stores(0);
+        let now = Utc::now();
+        let session = session_expiring_in(now, Duration::minutes(28));
+        inner.insert(session.clone());
+
+        let refreshed = cached.refresh(&session, now).await.unwrap().unwrap();
+
+        assert_eq!(refreshed.expires_at, session.expires_at);
+        let stored = inner.find(session.id).await.unwrap().unwrap();
+        assert_eq!(stored.expires_at, session.expires_at);
+    }
+
+    #[actix_web::test]
+    async fn refresh_above_threshold_does_not_read_the_store() {
+        let (inner, cached) = stores(0);
+        let now = Utc::now();
+        // found by the caller, but gone from the store since
+        let session = session_expiring_in(now, Duration::minutes(28));
+
+        let refreshed = cached.refresh(&session, now).await.unwrap().unwrap();
+
+        assert_eq!(refreshed.expires_at, session.expires_at);
+        assert!(inner.find(session.id).await.unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn refresh_is_written_below_threshold() {
+        let (inner, cached) = stores(0);
+        let now = Utc::now();
+        let session = session_expiring_in(now, Duration::minutes(10));
+        inner.insert(session.clone());
+
+        let refreshed = cached.refresh(&session, now).await.unwrap().unwrap();
+
+        assert_eq!(refreshed.expires_at, now + session_lifetime());
+        let stored = inner.find(session.id).await.unwrap().unwrap();
+        assert_eq!(stored.expires_at, refreshed.expires_at);
+    }
+
+    #[actix_web::test]
+    async fn delete_invalidates_cache() {
+        let (inner, cached) = stores(60);
+        let session = cached.create(Uuid::new_v4(), Utc::now()).await.unwrap();
+
+        // deleted behind the cache's back, the cached session is still served
+        inner.delete(session.id).await.unwrap();
+        assert!(cached.find(session.id).await.unwrap().is_some());
+
+        cached.delete(session.id).await.unwrap();
+        assert!(cached.find(session.id).await.unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn delete_for_account_invalidates_cache() {
+        let (inner, cached) = stores(60);
+        let now = Utc::now();
+        let account_id = Uuid::new_v4();
+        let first = cached.create(account_id, now).await.unwrap();
+        let second = cached.create(account_id, now).await.unwrap();
+        let other = cached.create(Uuid::new_v4(), now).await.unwrap();
+
+        assert_eq!(cached.delete_for_account(account_id).await.unwrap(), 2);
+
+        assert!(cached.find(first.id).await.unwrap().is_none());
+        assert!(cached.find(second.id).await.unwrap().is_none());
+        assert!(cached.find(other.id).await.unwrap().is_some());
+        assert_eq!(inner.count_active(now).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,165 @@
+use async_trait::async_trait;
+use sqlx::types::chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use super::{session_lifetime, RepositoryResult, Session, SessionStore};
+#[cfg(test)]
+use super::{Account, AccountRepository, Lang};
+
+/// Accounts kept in memory, for testing handlers without a database.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryAccountRepository {
+    accounts: Mutex<HashMap<Uuid, Account>>,
+}
+
+#[cfg(test)]
+impl MemoryAccountRepository {
+    pub fn insert(&self, account: Account) {
+        self.accounts.lock().unwrap().insert(account.id, account);
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl AccountRepository for MemoryAccountRepository {
+    async fn find_by_name(&self, account_name: &str) -> RepositoryResult<Option<Account>> {
+        let accounts = self.accounts.lock().unwrap();
+        Ok(accounts
+            .values()
+            .find(|account| account.account_name == account_name)
+            .cloned())
+    }
+
+    async fn find_by_id(&self, account_id: Uuid) -> RepositoryResult<Option<Account>> {
+        Ok(self.accounts.lock().unwrap().get(&account_id).cloned())
+    }
+
+    async fn set_preferred_lang(
+        &self,
+        account_id: Uuid,
+        lang: Lang,
+    ) -> RepositoryResult<Option<Account>> {
+        let mut accounts = self.accounts.lock().unwrap();
+        Ok(accounts.get_mut(&account_id).map(|account| {
+            account.preferred_lang = lang;
+            account.clone()
+        }))
+    }
+}
+
+/// Sessions kept in memory, for tests and deployments with a single instance.
+#[derive(Default)]
+pub struct MemorySessionStore {
+    sessions: Mutex<HashMap<Uuid, Session>>,
+}
+
+impl MemorySessionStore {
+    pub fn insert(&self, session: Session) {
+        self.sessions.lock().unwrap().insert(session.id, session);
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn create(&self, account_id: Uuid, now: DateTime<Utc>) -> RepositoryResult<Session> {
+        let session = Session {
+            id: Uuid::new_v4(),
+            account_id,
+            expires_at: now + session_lifetime(),
+        };
+        self.insert(session.clone());
+        Ok(session)
+    }
+
+    async fn find(&self, session_id: Uuid) -> RepositoryResult<Option<Session>> {
+        Ok(self.sessions.lock().unwrap().get(&session_id).cloned())
+    }
+
+    async fn refresh(
+        &self,
+        session: &Session,
+        now: DateTime<Utc>,
+    ) -> RepositoryResult<Option<Session>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        Ok(sessions.get_mut(&session.id).map(|session| {
+            session.expires_at = now + session_lifetime();
+            session.clone()
+        }))
+    }
+
+    async fn delete(&self, session_id: Uuid) -> RepositoryResult<()> {
+        self.sessions.lock().unwrap().remove(&session_id);
+        Ok(())
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> RepositoryResult<u64> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, session| session.expires_at >= now);
+        Ok((before - sessions.len()) as u64)
+    }
+
+    async fn delete_for_account(&self, account_id: Uuid) -> RepositoryResult<u64> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, session| session.account_id != account_id);
+        Ok((before - sessions.len()) as u64)
+    }
+
+    async fn delete_all(&self) -> RepositoryResult<u64> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let deleted = sessions.len();
+        sessions.clear();
+        Ok(deleted as u64)
+    }
+
+    async fn list(&self) -> RepositoryResult<Vec<Session>> {
+        let mut sessions: Vec<Session> = self.sessions.lock().unwrap().values().cloned().collect();
+        sessions.sort_by_key(|session| session.expires_at);
+        Ok(sessions)
+    }
+
+    async fn count_active(&self, now: DateTime<Utc>) -> RepositoryResult<u64> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(sessions
+            .values()
+            .filter(|session| session.expires_at > now)
+            .count() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[actix_web::test]
+    async fn purge_expired_keeps_active_sessions() {
+        let store = MemorySessionStore::default();
+        let now = Utc::now();
+        let active = store.create(Uuid::new_v4(), now).await.unwrap();
+        let expired_id = Uuid::new_v4();
+        store.insert(Session {
+            id: expired_id,
+            account_id: Uuid::new_v4(),
+            expires_at: now - Duration::seconds(1),
+        });
+
+        assert_eq!(store.purge_expired(now).await.unwrap(), 1);
+        assert!(store.find(active.id).await.unwrap().is_some());
+        assert!(store.find(expired_id).await.unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn refresh_of_deleted_session_finds_nothing() {
+        let store = MemorySessionStore::default();
+        let now = Utc::now();
+        let session = store.create(Uuid::new_v4(), now).await.unwrap();
+        store.delete(session.id).await.unwrap();
+
+        assert!(store.refresh(&session, now).await.unwrap().is_none());
+    }
+}
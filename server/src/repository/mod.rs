@@ -0,0 +1,209 @@
+mod cached;
+mod memory;
+mod postgres;
+mod redis;
+
+pub use self::redis::*;
+pub use cached::*;
+pub use memory::*;
+pub use postgres::*;
+
+use actix_web::rt::time::sleep;
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::Duration;
+use log::{log, Level};
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::clock::Clock;
+use crate::configuration::SessionSettings;
+use crate::metrics::Metrics;
+use crate::shutdown::ShutdownSignal;
+
+/// Lifetime of new and refreshed sessions.
+pub fn session_lifetime() -> Duration {
+    Duration::minutes(30)
+}
+
+/// Errors of the repositories, which handlers turn into `ApiErrorType::DbError`.
+#[derive(Debug)]
+pub enum RepositoryError {
+    Database(sqlx::Error),
+    Redis(::redis::RedisError),
+}
+
+impl Display for RepositoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepositoryError::Database(error) => Display::fmt(error, f),
+            RepositoryError::Redis(error) => Display::fmt(error, f),
+        }
+    }
+}
+
+impl std::error::Error for RepositoryError {}
+
+impl From<sqlx::Error> for RepositoryError {
+    fn from(error: sqlx::Error) -> Self {
+        RepositoryError::Database(error)
+    }
+}
+
+impl From<::redis::RedisError> for RepositoryError {
+    fn from(error: ::redis::RedisError) -> Self {
+        RepositoryError::Redis(error)
+    }
+}
+
+pub type RepositoryResult<T> = Result<T, RepositoryError>;
+
+/// Logs errors of the backends where they happen, so callers only need to map them.
+fn logged(error: impl Into<RepositoryError>, action: &str, data: impl Debug) -> RepositoryError {
+    let error = error.into();
+    log!(
+        Level::Error,
+        "Error: {}, while {}, Data: {:?}",
+        error,
+        action,
+        data
+    );
+    error
+}
+
+#[derive(sqlx::Type, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[sqlx(type_name = "lang", rename_all = "lowercase")]
+pub enum Lang {
+    De,
+    En,
+}
+
+#[derive(Clone, Debug)]
+pub struct Account {
+    pub id: Uuid,
+    pub account_name: String,
+    pub pw_hash: String,
+    pub name: String,
+    pub preferred_lang: Lang,
+    pub disabled: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub id: Uuid,
+    pub account_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[async_trait]
+pub trait AccountRepository: Send + Sync {
+    async fn find_by_name(&self, account_name: &str) -> RepositoryResult<Option<Account>>;
+
+    async fn find_by_id(&self, account_id: Uuid) -> RepositoryResult<Option<Account>>;
+
+    /// Returns the updated account, or `None` if there is no such account.
+    async fn set_preferred_lang(
+        &self,
+        account_id: Uuid,
+        lang: Lang,
+    ) -> RepositoryResult<Option<Account>>;
+}
+
+/// Where sessions are kept.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionBackend {
+    /// The `session` table of the database.
+    #[default]
+    Postgres,
+    /// Memory of the process, lost on restart and not shared between instances.
+    Memory,
+    /// A Redis compatible server, expiring sessions by their key's TTL.
+    Redis,
+}
+
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Starts a session expiring `session_lifetime` after `now`.
+    async fn create(&self, account_id: Uuid, now: DateTime<Utc>) -> RepositoryResult<Session>;
+
+    /// Finds the session, which may have expired but not been purged yet.
+    async fn find(&self, session_id: Uuid) -> RepositoryResult<Option<Session>>;
+
+    /// Moves the expiry of the session found before to `session_lifetime` after `now`, unless
+    /// it has been deleted in the meantime.
+    async fn refresh(
+        &self,
+        session: &Session,
+        now: DateTime<Utc>,
+    ) -> RepositoryResult<Option<Session>>;
+
+    async fn delete(&self, session_id: Uuid) -> RepositoryResult<()>;
+
+    /// Deletes sessions expired before `now`, returning how many. Stores expiring them by
+    /// themselves return 0.
+    async fn purge_expired(&self, now: DateTime<Utc>) -> RepositoryResult<u64>;
+
+    /// Deletes all sessions of the account, expired or not, returning how many.
+    async fn delete_for_account(&self, account_id: Uuid) -> RepositoryResult<u64>;
+
+    /// Deletes all sessions, logging out every user, returning how many.
+    async fn delete_all(&self) -> RepositoryResult<u64>;
+
+    /// All sessions, including expired ones not purged yet, ordered by expiry.
+    async fn list(&self) -> RepositoryResult<Vec<Session>>;
+
+    /// Number of sessions expiring after `now`.
+    async fn count_active(&self, now: DateTime<Utc>) -> RepositoryResult<u64>;
+}
+
+/// Creates the session store selected by `settings`, behind the cache and refresh threshold.
+pub async fn session_store(
+    settings: &SessionSettings,
+    db_pool: &PgPool,
+) -> anyhow::Result<Arc<dyn SessionStore>> {
+    let backend: Arc<dyn SessionStore> = match settings.backend {
+        SessionBackend::Postgres => Arc::new(PgSessionStore::new(db_pool.clone())),
+        SessionBackend::Memory => Arc::new(MemorySessionStore::default()),
+        SessionBackend::Redis => {
+            let url = settings
+                .redis_url
+                .as_ref()
+                .context("`sessions.redis_url` is missing")?;
+            Arc::new(
+                RedisSessionStore::connect(url.expose())
+                    .await
+                    .context("Couldn't connect to Redis")?,
+            )
+        }
+    };
+    Ok(Arc::new(CachedSessionStore::new(
+        backend,
+        std::time::Duration::from_secs(settings.cache_ttl_seconds),
+        Duration::seconds(settings.refresh_threshold_seconds as i64),
+    )))
+}
+
+/// Background task purging expired sessions every `interval` until shutdown.
+pub async fn purge_expired_sessions(
+    sessions: Arc<dyn SessionStore>,
+    clock: Arc<dyn Clock>,
+    metrics: Metrics,
+    interval: std::time::Duration,
+    mut shutdown: ShutdownSignal,
+) {
+    loop {
+        // errors are logged by the store, the next round will try again
+        if let Ok(purged) = sessions.purge_expired(clock.now()).await {
+            metrics.add_sessions_cleaned_up(purged);
+        }
+        tokio::select! {
+            _ = sleep(interval) => (),
+            _ = shutdown.recv() => return,
+        }
+    }
+}
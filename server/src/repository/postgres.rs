@@ -0,0 +1,308 @@
+use async_trait::async_trait;
+use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::{query, query_as, query_scalar, PgPool};
+use uuid::Uuid;
+
+use super::{
+    logged, session_lifetime, Account, AccountRepository, Lang, RepositoryResult, Session,
+    SessionStore,
+};
+
+pub struct PgAccountRepository {
+    db_pool: PgPool,
+}
+
+impl PgAccountRepository {
+    pub fn new(db_pool: PgPool) -> Self {
+        PgAccountRepository { db_pool }
+    }
+}
+
+#[async_trait]
+impl AccountRepository for PgAccountRepository {
+    async fn find_by_name(&self, account_name: &str) -> RepositoryResult<Option<Account>> {
+        query_as!(
+            Account,
+            // language=postgresql
+            r#"
+                SELECT
+                    id,
+                    account_name,
+                    pw_hash,
+                    name,
+                    preferred_language AS "preferred_lang: Lang",
+                    disabled
+                FROM account
+                WHERE account_name = $1
+            "#,
+            account_name
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+        .map_err(|error| logged(error, "finding account", account_name))
+    }
+
+    async fn find_by_id(&self, account_id: Uuid) -> RepositoryResult<Option<Account>> {
+        query_as!(
+            Account,
+            // language=postgresql
+            r#"
+                SELECT
+                    id,
+                    account_name,
+                    pw_hash,
+                    name,
+                    preferred_language AS "preferred_lang: Lang",
+                    disabled
+                FROM account
+                WHERE id = $1
+            "#,
+            account_id
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+        .map_err(|error| logged(error, "retrieving account", account_id))
+    }
+
+    async fn set_preferred_lang(
+        &self,
+        account_id: Uuid,
+        lang: Lang,
+    ) -> RepositoryResult<Option<Account>> {
+        query_as!(
+            Account,
+            // language=postgresql
+            r#"
+                UPDATE account SET preferred_language = $2
+                WHERE id = $1
+                RETURNING
+                    id,
+                    account_name,
+                    pw_hash,
+                    name,
+                    preferred_language AS "preferred_lang: Lang",
+                    disabled
+            "#,
+            account_id,
+            lang as Lang
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+        .map_err(|error| logged(error, "updating preferred language", account_id))
+    }
+}
+
+pub struct PgSessionStore {
+    db_pool: PgPool,
+}
+
+impl PgSessionStore {
+    pub fn new(db_pool: PgPool) -> Self {
+        PgSessionStore { db_pool }
+    }
+}
+
+#[async_trait]
+impl SessionStore for PgSessionStore {
+    async fn create(&self, account_id: Uuid, now: DateTime<Utc>) -> RepositoryResult<Session> {
+        query_as!(
+            Session,
+            // language=postgresql
+            r#"
+                INSERT INTO session (account_id, expires_at) VALUES ($1, $2)
+                RETURNING id, account_id, expires_at
+            "#,
+            account_id,
+            now + session_lifetime()
+        )
+        .fetch_one(&self.db_pool)
+        .await
+        .map_err(|error| logged(error, "inserting session row", account_id))
+    }
+
+    async fn find(&self, session_id: Uuid) -> RepositoryResult<Option<Session>> {
+        query_as!(
+            Session,
+            // language=postgresql
+            r#"
+                SELECT id, account_id, expires_at FROM session WHERE id = $1
+            "#,
+            session_id
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+        .map_err(|error| logged(error, "finding session", session_id))
+    }
+
+    async fn refresh(
+        &self,
+        session: &Session,
+        now: DateTime<Utc>,
+    ) -> RepositoryResult<Option<Session>> {
+        query_as!(
+            Session,
+            // language=postgresql
+            r#"
+                UPDATE session SET expires_at = $2
+                WHERE id = $1 RETURNING id, account_id, expires_at
+            "#,
+            session.id,
+            now + session_lifetime()
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+        .map_err(|error| logged(error, "updating session", session.id))
+    }
+
+    async fn delete(&self, session_id: Uuid) -> RepositoryResult<()> {
+        query!(
+            // language=postgresql
+            r#"
+                DELETE FROM session WHERE id = $1
+            "#,
+            session_id
+        )
+        .execute(&self.db_pool)
+        .await
+        .map(|_| ())
+        .map_err(|error| logged(error, "deleting session", session_id))
+    }
+
+    async fn purge_expired(&self, now: DateTime<Utc>) -> RepositoryResult<u64> {
+        query!(
+            // language=postgresql
+            r#"
+                DELETE FROM session WHERE expires_at < $1
+            "#,
+            now
+        )
+        .execute(&self.db_pool)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|error| logged(error, "purging expired sessions", ()))
+    }
+
+    async fn delete_for_account(&self, account_id: Uuid) -> RepositoryResult<u64> {
+        query!(
+            // language=postgresql
+            r#"
+                DELETE FROM session WHERE account_id = $1
+            "#,
+            account_id
+        )
+        .execute(&self.db_pool)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|error| logged(error, "deleting sessions of account", account_id))
+    }
+
+    async fn delete_all(&self) -> RepositoryResult<u64> {
+        query!(
+            // language=postgresql
+            r#"
+                DELETE FROM session
+            "#
+        )
+        .execute(&self.db_pool)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|error| logged(error, "deleting all sessions", ()))
+    }
+
+    async fn list(&self) -> RepositoryResult<Vec<Session>> {
+        query_as!(
+            Session,
+            // language=postgresql
+            r#"
+                SELECT id, account_id, expires_at FROM session ORDER BY expires_at
+            "#
+        )
+        .fetch_all(&self.db_pool)
+        .await
+        .map_err(|error| logged(error, "listing sessions", ()))
+    }
+
+    async fn count_active(&self, now: DateTime<Utc>) -> RepositoryResult<u64> {
+        query_scalar!(
+            // language=postgresql
+            r#"
+                SELECT COUNT(*) AS "count!" FROM session WHERE expires_at > $1
+            "#,
+            now
+        )
+        .fetch_one(&self.db_pool)
+        .await
+        .map(|count| count as u64)
+        .map_err(|error| logged(error, "counting active sessions", now))
+    }
+}
+
+/// Run with `cargo test -- --ignored` against a fresh database created by `sqlx::test` on the
+/// server in `DATABASE_URL`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+    use sqlx::Executor;
+
+    /// Far from UTC, so that a timestamp without time zone would be off by 14 hours.
+    const TIME_ZONE: &str = "Pacific/Kiritimati";
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres server"]
+    async fn session_expiry_is_independent_of_database_time_zone(
+        pool_options: PgPoolOptions,
+        connect_options: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        // sqlx sets `TimeZone=UTC` on connecting, so the zone is changed afterwards
+        let db_pool = pool_options
+            .after_connect(|connection, _| {
+                Box::pin(async move {
+                    connection
+                        .execute(format!("SET TIME ZONE '{}'", TIME_ZONE).as_str())
+                        .await
+                        .map(|_| ())
+                })
+            })
+            .connect_with(connect_options)
+            .await?;
+        let time_zone: String = sqlx::query_scalar("SHOW timezone")
+            .fetch_one(&db_pool)
+            .await?;
+        assert_eq!(time_zone, TIME_ZONE);
+        let account_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO account (account_name, pw_hash, name) VALUES ('tester', '', 'Test User') \
+             RETURNING id",
+        )
+        .fetch_one(&db_pool)
+        .await?;
+        let sessions = PgSessionStore::new(db_pool.clone());
+
+        let now = Utc::now();
+        let session = sessions.create(account_id, now).await.unwrap();
+        let lifetime = session.expires_at - now;
+        assert!(lifetime > session_lifetime() - Duration::seconds(1));
+        assert!(lifetime <= session_lifetime());
+        let found = sessions.find(session.id).await.unwrap().unwrap();
+        assert_eq!(found.expires_at, session.expires_at);
+        // the database agrees when comparing with its own clock
+        let active: bool = sqlx::query_scalar(
+            "SELECT expires_at > CURRENT_TIMESTAMP + INTERVAL '29 minutes' FROM session \
+             WHERE id = $1",
+        )
+        .bind(session.id)
+        .fetch_one(&db_pool)
+        .await?;
+        assert!(active);
+
+        sqlx::query("INSERT INTO session (account_id, expires_at) VALUES ($1, $2)")
+            .bind(account_id)
+            .bind(now - Duration::minutes(1))
+            .execute(&db_pool)
+            .await?;
+        assert_eq!(sessions.purge_expired(now).await.unwrap(), 1);
+        assert!(sessions.find(session.id).await.unwrap().is_some());
+        Ok(())
+    }
+}
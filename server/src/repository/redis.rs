@@ -0,0 +1,309 @@
+use async_trait::async_trait;
+use chrono::Duration;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Client};
+use sqlx::types::chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{logged, session_lifetime, RepositoryResult, Session, SessionStore};
+
+const KEY_PREFIX: &str = "session:";
+
+/// Keys outlive the expiry of their session by this much, so that a client using an expired
+/// session is told so instead of the session being unknown.
+fn expired_grace() -> Duration {
+    Duration::minutes(10)
+}
+
+/// Sessions kept in a Redis compatible server as `session:<id>` keys holding the account id and
+/// expiry, removed by the server once their TTL runs out.
+pub struct RedisSessionStore {
+    // reconnects by itself and is cheap to clone for each command
+    connection: ConnectionManager,
+}
+
+impl RedisSessionStore {
+    /// Connects to a server given by a URL like `redis://:password@localhost:6379/0`.
+    pub async fn connect(url: &str) -> RepositoryResult<Self> {
+        let client = Client::open(url)?;
+        Ok(RedisSessionStore {
+            connection: ConnectionManager::new(client).await?,
+        })
+    }
+
+    async fn store(
+        &self,
+        session: &Session,
+        now: DateTime<Utc>,
+        only_existing: bool,
+    ) -> RepositoryResult<bool> {
+        let ttl = session.expires_at - now + expired_grace();
+        let mut command = redis::cmd("SET");
+        command
+            .arg(key(session.id))
+            .arg(value(session))
+            .arg("PX")
+            .arg(ttl.num_milliseconds().max(1));
+        if only_existing {
+            command.arg("XX");
+        }
+        let stored: Option<String> = command.query_async(&mut self.connection.clone()).await?;
+        Ok(stored.is_some())
+    }
+
+    /// All sessions, found by scanning the keys, which only suits the `session` commands and
+    /// metrics.
+    async fn scan(&self) -> RepositoryResult<Vec<Session>> {
+        let mut connection = self.connection.clone();
+        let mut keys: Vec<String> = Vec::new();
+        let mut iter = connection
+            .scan_match::<_, String>(format!("{}*", KEY_PREFIX))
+            .await?;
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        drop(iter);
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        // keys expiring during the scan come back as nil
+        let values: Vec<Option<String>> = redis::cmd("MGET")
+            .arg(&keys)
+            .query_async(&mut connection)
+            .await?;
+        let mut sessions: Vec<Session> = keys
+            .iter()
+            .zip(values)
+            .filter_map(|(key, value)| {
+                let session_id = Uuid::parse_str(key.strip_prefix(KEY_PREFIX)?).ok()?;
+                parse(session_id, &value?)
+            })
+            .collect();
+        sessions.sort_by_key(|session| session.expires_at);
+        Ok(sessions)
+    }
+
+    async fn delete_sessions(&self, sessions: &[Session]) -> RepositoryResult<u64> {
+        if sessions.is_empty() {
+            return Ok(0);
+        }
+        let keys: Vec<String> = sessions.iter().map(|session| key(session.id)).collect();
+        Ok(self.connection.clone().del(keys).await?)
+    }
+}
+
+fn key(session_id: Uuid) -> String {
+    format!("{}{}", KEY_PREFIX, session_id)
+}
+
+fn value(session: &Session) -> String {
+    format!(
+        "{} {}",
+        session.account_id,
+        session.expires_at.timestamp_millis()
+    )
+}
+
+fn parse(session_id: Uuid, value: &str) -> Option<Session> {
+    let (account_id, expires_at) = value.split_once(' ')?;
+    Some(Session {
+        id: session_id,
+        account_id: Uuid::parse_str(account_id).ok()?,
+        expires_at: DateTime::from_timestamp_millis(expires_at.parse().ok()?)?,
+    })
+}
+
+#[async_trait]
+impl SessionStore for RedisSessionStore {
+    async fn create(&self, account_id: Uuid, now: DateTime<Utc>) -> RepositoryResult<Session> {
+        let session = Session {
+            id: Uuid::new_v4(),
+            account_id,
+            expires_at: now + session_lifetime(),
+        };
+        self.store(&session, now, false)
+            .await
+            .map_err(|error| logged(error, "storing session", account_id))?;
+        Ok(session)
+    }
+
+    async fn find(&self, session_id: Uuid) -> RepositoryResult<Option<Session>> {
+        let stored: Option<String> = self
+            .connection
+            .clone()
+            .get(key(session_id))
+            .await
+            .map_err(|error| logged(error, "finding session", session_id))?;
+        Ok(stored.and_then(|stored| parse(session_id, &stored)))
+    }
+
+    async fn refresh(
+        &self,
+        session: &Session,
+        now: DateTime<Utc>,
+    ) -> RepositoryResult<Option<Session>> {
+        let mut session = session.clone();
+        session.expires_at = now + session_lifetime();
+        // `XX` doesn't bring back a session deleted in the meantime, e.g. by a logout
+        let stored = self
+            .store(&session, now, true)
+            .await
+            .map_err(|error| logged(error, "updating session", session.id))?;
+        Ok(stored.then_some(session))
+    }
+
+    async fn delete(&self, session_id: Uuid) -> RepositoryResult<()> {
+        self.connection
+            .clone()
+            .del(key(session_id))
+            .await
+            .map_err(|error| logged(error, "deleting session", session_id))
+    }
+
+    async fn purge_expired(&self, _now: DateTime<Utc>) -> RepositoryResult<u64> {
+        // keys expire by their TTL
+        Ok(0)
+    }
+
+    async fn delete_for_account(&self, account_id: Uuid) -> RepositoryResult<u64> {
+        let mut sessions = self
+            .scan()
+            .await
+            .map_err(|error| logged(error, "listing sessions of account", account_id))?;
+        sessions.retain(|session| session.account_id == account_id);
+        self.delete_sessions(&sessions)
+            .await
+            .map_err(|error| logged(error, "deleting sessions of account", account_id))
+    }
+
+    async fn delete_all(&self) -> RepositoryResult<u64> {
+        let sessions = self
+            .scan()
+            .await
+            .map_err(|error| logged(error, "listing sessions", ()))?;
+        self.delete_sessions(&sessions)
+            .await
+            .map_err(|error| logged(error, "deleting all sessions", ()))
+    }
+
+    async fn list(&self) -> RepositoryResult<Vec<Session>> {
+        self.scan()
+            .await
+            .map_err(|error| logged(error, "listing sessions", ()))
+    }
+
+    async fn count_active(&self, now: DateTime<Utc>) -> RepositoryResult<u64> {
+        let sessions = self
+            .scan()
+            .await
+            .map_err(|error| logged(error, "counting active sessions", now))?;
+        Ok(sessions
+            .iter()
+            .filter(|session| session.expires_at > now)
+            .count() as u64)
+    }
+}
+
+/// Run with `cargo test -- --ignored` against the server in `REDIS_URL`, or one on localhost.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn store() -> RedisSessionStore {
+        let url =
+            std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+        RedisSessionStore::connect(&url).await.unwrap()
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a Redis server"]
+    async fn created_session_is_found_with_ttl() {
+        let store = store().await;
+        let account_id = Uuid::new_v4();
+        let session = store.create(account_id, Utc::now()).await.unwrap();
+
+        let found = store.find(session.id).await.unwrap().unwrap();
+        assert_eq!(found.account_id, account_id);
+        assert_eq!(
+            found.expires_at.timestamp_millis(),
+            session.expires_at.timestamp_millis()
+        );
+        let ttl: i64 = store
+            .connection
+            .clone()
+            .pttl(key(session.id))
+            .await
+            .unwrap();
+        assert!(
+            ttl > (session_lifetime() + expired_grace() - Duration::minutes(1)).num_milliseconds()
+        );
+
+        store.delete(session.id).await.unwrap();
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a Redis server"]
+    async fn deleted_session_is_neither_found_nor_refreshed() {
+        let store = store().await;
+        let now = Utc::now();
+        let session = store.create(Uuid::new_v4(), now).await.unwrap();
+        store.delete(session.id).await.unwrap();
+
+        assert!(store.find(session.id).await.unwrap().is_none());
+        assert!(store.refresh(&session, now).await.unwrap().is_none());
+        let exists: bool = store
+            .connection
+            .clone()
+            .exists(key(session.id))
+            .await
+            .unwrap();
+        assert!(!exists);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a Redis server"]
+    async fn sessions_of_account_are_listed_and_deleted() {
+        let store = store().await;
+        let now = Utc::now();
+        let account_id = Uuid::new_v4();
+        let first = store.create(account_id, now).await.unwrap();
+        let second = store.create(account_id, now).await.unwrap();
+        let other = store.create(Uuid::new_v4(), now).await.unwrap();
+
+        let listed: Vec<Uuid> = store
+            .list()
+            .await
+            .unwrap()
+            .iter()
+            .map(|session| session.id)
+            .collect();
+        assert!([first.id, second.id, other.id]
+            .iter()
+            .all(|id| listed.contains(id)));
+
+        assert_eq!(store.delete_for_account(account_id).await.unwrap(), 2);
+        assert!(store.find(first.id).await.unwrap().is_none());
+        assert!(store.find(second.id).await.unwrap().is_none());
+        assert!(store.find(other.id).await.unwrap().is_some());
+
+        store.delete(other.id).await.unwrap();
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a Redis server"]
+    async fn expired_session_is_found_until_its_key_expires() {
+        let store = store().await;
+        let now = Utc::now();
+        let session = Session {
+            id: Uuid::new_v4(),
+            account_id: Uuid::new_v4(),
+            expires_at: now - Duration::minutes(1),
+        };
+        store.store(&session, now, false).await.unwrap();
+
+        let found = store.find(session.id).await.unwrap().unwrap();
+        assert!(found.expires_at < now);
+
+        store.delete(session.id).await.unwrap();
+    }
+}
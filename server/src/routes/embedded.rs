@@ -0,0 +1,63 @@
+use actix_web::http::header::{self, EntityTag, IfNoneMatch};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use bytes::Bytes;
+use rust_embed::RustEmbed;
+use std::borrow::Cow;
+
+use super::static_content::{accepts_encoding, PRECOMPRESSED};
+
+/// The built client, embedded at compile time so the binary can be deployed on its own.
+#[derive(RustEmbed)]
+#[folder = "../public/"]
+struct Client;
+
+pub fn read(relative_path: &str) -> Option<Vec<u8>> {
+    Client::get(relative_path).map(|file| file.data.into_owned())
+}
+
+/// Responds with the embedded file at `relative_path` (using `/` as separator), preferring a
+/// precompressed sibling if the client accepts it. Returns `None` if there is no such file.
+pub fn respond(
+    request: &HttpRequest,
+    precompressed: bool,
+    relative_path: &str,
+) -> Option<HttpResponse> {
+    let file = Client::get(relative_path)?;
+    let content_type = file.metadata.mimetype().to_owned();
+    let (file, encoding) = PRECOMPRESSED
+        .into_iter()
+        .filter(|_| precompressed)
+        .filter(|(_, encoding)| accepts_encoding(request, encoding.as_str()))
+        .find_map(|(extension, encoding)| {
+            Client::get(&format!("{}.{}", relative_path, extension))
+                .map(|sibling| (sibling, Some(encoding)))
+        })
+        .unwrap_or((file, None));
+
+    let etag = EntityTag::new_strong(hex::encode(file.metadata.sha256_hash()));
+    let not_modified = match request.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+    };
+    if not_modified {
+        return Some(
+            HttpResponse::NotModified()
+                .insert_header(header::ETag(etag))
+                .finish(),
+        );
+    }
+
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(content_type)
+        .insert_header(header::ETag(etag));
+    if let Some(encoding) = encoding {
+        response.insert_header((header::CONTENT_ENCODING, encoding.as_str()));
+    }
+    let body = match file.data {
+        Cow::Borrowed(data) => Bytes::from_static(data),
+        Cow::Owned(data) => Bytes::from(data),
+    };
+    Some(response.body(body))
+}
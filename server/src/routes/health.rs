@@ -0,0 +1,140 @@
+use actix_web::rt::time::timeout;
+use actix_web::web::Data;
+use actix_web::HttpResponse;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::database::{schema_status, SchemaStatus};
+use crate::logging;
+
+/// Time a single readiness check may take before it counts as failing.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Shared between all workers, so that every one of them reports not being ready once
+/// shutting down has begun.
+#[derive(Default)]
+pub struct Readiness {
+    shutting_down: AtomicBool,
+}
+
+impl Readiness {
+    pub fn set_shutting_down(&self) {
+        self.shutting_down.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Ok,
+    Failing,
+}
+
+#[derive(Serialize)]
+struct Check {
+    status: Status,
+    latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    status: Status,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    checks: BTreeMap<&'static str, Check>,
+}
+
+/// Liveness: answers as long as the process is able to serve requests.
+pub async fn liveness_handler() -> HttpResponse {
+    HttpResponse::Ok().json(HealthResponse {
+        status: Status::Ok,
+        checks: BTreeMap::new(),
+    })
+}
+
+/// Readiness: checks the database, its schema version and the log file, answering with 503 if
+/// any of them fails or the server is shutting down.
+pub async fn readiness_handler(db_pool: Data<PgPool>, readiness: Data<Readiness>) -> HttpResponse {
+    let mut checks = BTreeMap::new();
+    checks.insert(
+        "shutdown",
+        run_check(async {
+            if readiness.is_shutting_down() {
+                Err("Shutting down".to_string())
+            } else {
+                Ok(())
+            }
+        })
+        .await,
+    );
+    checks.insert("database", run_check(check_database(&db_pool)).await);
+    checks.insert("migrations", run_check(check_migrations(&db_pool)).await);
+    checks.insert(
+        "log_sink",
+        run_check(async {
+            if logging::sink_writable() {
+                Ok(())
+            } else {
+                Err("Log file isn't writable".to_string())
+            }
+        })
+        .await,
+    );
+
+    let status = if checks.values().all(|check| check.status == Status::Ok) {
+        Status::Ok
+    } else {
+        Status::Failing
+    };
+    let body = HealthResponse { status, checks };
+    match status {
+        Status::Ok => HttpResponse::Ok().json(body),
+        Status::Failing => HttpResponse::ServiceUnavailable().json(body),
+    }
+}
+
+async fn run_check(check: impl Future<Output = Result<(), String>>) -> Check {
+    let start = Instant::now();
+    let result = match timeout(CHECK_TIMEOUT, check).await {
+        Ok(result) => result,
+        Err(_) => Err("Timed out".to_string()),
+    };
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+    match result {
+        Ok(()) => Check {
+            status: Status::Ok,
+            latency_ms,
+            error: None,
+        },
+        Err(error) => Check {
+            status: Status::Failing,
+            latency_ms,
+            error: Some(error),
+        },
+    }
+}
+
+async fn check_database(db_pool: &PgPool) -> Result<(), String> {
+    sqlx::query("SELECT 1")
+        .execute(db_pool)
+        .await
+        .map(|_| ())
+        .map_err(|error| error.to_string())
+}
+
+async fn check_migrations(db_pool: &PgPool) -> Result<(), String> {
+    match schema_status(db_pool).await {
+        Ok(SchemaStatus::UpToDate) => Ok(()),
+        Ok(status) => Err(status.to_string()),
+        Err(error) => Err(error.to_string()),
+    }
+}
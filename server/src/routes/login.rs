@@ -1,18 +1,21 @@
-use crate::validation::LoginData;
+use crate::validation::{AccountPassword, LoginData};
 use actix_web::web::Data;
 use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
 use base64::engine::general_purpose;
 use base64::Engine;
-use bcrypt::verify;
+use bcrypt::{hash, verify, BcryptResult, DEFAULT_COST};
 use bytes::Bytes;
 use log::{log, Level};
 use serde::{Deserialize, Serialize};
 use simple_crypt;
-use sqlx::{query, PgPool};
+use std::time::Instant;
 use uuid::Uuid;
 
 use crate::api_error::{return_early, ApiError, ApiErrorType};
-use crate::authorisation::HandlerResponse;
+use crate::authorisation::{AccountId, HandlerResponse};
+use crate::clock::Clock;
+use crate::metrics::Metrics;
+use crate::repository::{AccountRepository, SessionStore};
 
 pub type ExpiresAt = i64;
 #[derive(Serialize, Deserialize, Debug)]
@@ -29,8 +32,11 @@ pub struct LoginRequest {
 pub async fn login_handler(
     request: HttpRequest,
     req_json_body: web::Json<LoginRequest>,
-    db_pool: Data<PgPool>,
+    accounts: Data<dyn AccountRepository>,
+    sessions: Data<dyn SessionStore>,
     session_secret: Data<Bytes>,
+    metrics: Data<Metrics>,
+    clock: Data<dyn Clock>,
 ) -> HttpResponse {
     let into_api_error = ApiError::get_into(&request);
 
@@ -43,11 +49,12 @@ pub async fn login_handler(
                 &error,
                 request.peer_addr().unwrap().ip()
             );
+            metrics.record_login("failure");
             return return_early(into_api_error(ApiErrorType::Unauthorized));
         }
     };
 
-    let account_id = match authenticate(&login_data, &*db_pool.as_ref()).await {
+    let account_id = match authenticate(&login_data, accounts.as_ref(), &metrics).await {
         Ok(Some(id)) => id,
         Ok(None) => {
             log!(
@@ -56,16 +63,14 @@ pub async fn login_handler(
                 login_data.account_name,
                 request.peer_addr().unwrap().ip()
             );
+            metrics.record_login("failure");
             return return_early(into_api_error(ApiErrorType::Unauthorized.into()));
         }
         Err(error) => {
             if error == ApiErrorType::DbError {
-                log!(
-                    Level::Error,
-                    "Error: {}, while authenticating credentials, Data: {:?}",
-                    error,
-                    login_data
-                )
+                metrics.record_login("error");
+            } else {
+                metrics.record_login("failure");
             }
             log!(
                 Level::Warn,
@@ -76,24 +81,12 @@ pub async fn login_handler(
         }
     };
 
-    let session_row = match query!(
-        // language=postgresql
-        r#"
-            INSERT INTO session (account_id) VALUES ($1) RETURNING id, expires_at
-        "#,
-        account_id
-    )
-    .fetch_one(&**db_pool)
-    .await
-    {
+    request.extensions_mut().insert(AccountId(account_id));
+
+    let session_row = match sessions.create(account_id, clock.now()).await {
         Ok(row) => row,
         Err(error) => {
-            log!(
-                Level::Error,
-                "Error: {}, while inserting session row, Data: {:?}",
-                error,
-                account_id
-            );
+            metrics.record_login("error");
             return return_early(into_api_error(error.into()));
         }
     };
@@ -107,44 +100,40 @@ pub async fn login_handler(
                 error,
                 session_row.id
             );
+            metrics.record_login("error");
             return return_early(into_api_error(ApiErrorType::Unauthorized));
         }
     };
     let session_token = general_purpose::URL_SAFE.encode(session_token_bytes);
     request
         .extensions_mut()
-        .insert::<ExpiresAt>(session_row.expires_at.and_utc().timestamp());
+        .insert::<ExpiresAt>(session_row.expires_at.timestamp());
 
     let res = HandlerResponse::Login(LoginResponse { session_token });
+    metrics.record_login("success");
     log!(Level::Info, "Logged in: {:?}", login_data.account_name.as_ref());
     HttpResponse::Ok().json(res)
 }
 
-async fn authenticate(cred: &LoginData, db_pool: &PgPool) -> Result<Option<Uuid>, ApiErrorType> {
-    let account_row = query!(
-        // language=postgresql
-        r#"
-            SELECT
-                id as account_id,
-                pw_hash,
-                name
-            FROM account
-            WHERE account_name = $1
-        "#,
-        cred.account_name.as_ref()
-    )
-    .fetch_optional(db_pool)
-    .await?;
+async fn authenticate(
+    cred: &LoginData,
+    accounts: &dyn AccountRepository,
+    metrics: &Metrics,
+) -> Result<Option<Uuid>, ApiErrorType> {
+    let account = match accounts.find_by_name(cred.account_name.as_ref()).await? {
+        Some(account) if !account.disabled => account,
+        _ => return Ok(None),
+    };
 
-    if account_row.is_none() {
-        Ok(None)
-    } else {
-        match verify(
-            cred.password.clone(),
-            account_row.as_ref().unwrap().pw_hash.as_str(),
-        ) {
-            Ok(true) => Ok(Some(account_row.unwrap().account_id)),
-            _ => Err(ApiErrorType::Unauthorized),
-        }
+    let start = Instant::now();
+    let verified = verify(cred.password.clone(), account.pw_hash.as_str());
+    metrics.observe_bcrypt_verify(start.elapsed());
+    match verified {
+        Ok(true) => Ok(Some(account.id)),
+        _ => Err(ApiErrorType::Unauthorized),
     }
 }
+
+pub fn hash_password(password: &AccountPassword) -> BcryptResult<String> {
+    hash(password, DEFAULT_COST)
+}
@@ -0,0 +1,18 @@
+use actix_web::web::Data;
+use actix_web::HttpResponse;
+use log::{log, Level};
+use prometheus::TEXT_FORMAT;
+use sqlx::PgPool;
+
+use crate::metrics::Metrics;
+
+/// Metrics in the Prometheus text format.
+pub async fn metrics_handler(metrics: Data<Metrics>, db_pool: Data<PgPool>) -> HttpResponse {
+    match metrics.render(&db_pool) {
+        Ok(body) => HttpResponse::Ok().content_type(TEXT_FORMAT).body(body),
+        Err(error) => {
+            log!(Level::Error, "Error: {}, while encoding metrics", error);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
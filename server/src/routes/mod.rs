@@ -1,9 +1,15 @@
+#[cfg(feature = "embed-client")]
+mod embedded;
+pub mod health;
 pub mod login;
+pub mod metrics;
 pub mod not_found;
 pub mod session;
 pub mod static_content;
 
+pub use health::*;
 pub use login::*;
+pub use metrics::*;
 pub use not_found::*;
 pub use session::*;
 pub use static_content::*;
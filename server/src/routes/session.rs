@@ -1,17 +1,9 @@
+use crate::api_error::{return_early, ApiError, ApiErrorType};
+use crate::authorisation::{AccountId, DBId, HandlerResponse};
+use crate::repository::{AccountRepository, Lang, SessionStore};
 use actix_web::web::{Data, Json};
 use actix_web::{HttpRequest, HttpResponse};
-use log::{log, Level};
 use serde::{Deserialize, Serialize};
-use sqlx::{query, PgPool};
-use crate::api_error::{return_early, ApiError, ApiErrorType};
-use crate::authorisation::{HandlerResponse, DBId};
-
-#[derive(sqlx::Type, Serialize, Debug, Deserialize)]
-#[sqlx(type_name = "lang", rename_all = "lowercase")]
-enum Lang {
-    De,
-    En,
-}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SessionResponse {
@@ -25,106 +17,65 @@ pub struct SessionRequest {
 }
 
 pub async fn session_handler(
-    db_pool: Data<PgPool>,
-    session_id: DBId,
+    accounts: Data<dyn AccountRepository>,
+    account_id: AccountId,
     request: HttpRequest,
 ) -> HttpResponse {
     let into_api_error = ApiError::get_into(&request);
-    let account_row = match query!(
-        // language=postgresql
-        r#"
-           SELECT
-               name,
-               preferred_language AS "preferred_lang: Lang"
-           FROM account a
-           JOIN session s on a.id = s.account_id
-           WHERE s.id = $1
-       "#,
-        *session_id
-    )
-    .fetch_one(&**db_pool)
-    .await
-    {
-        Ok(row) => row,
-        Err(error) => {
-            log!(
-                Level::Error,
-                "Error: {}, while retrieving account, Data: {:?}",
-                error,
-                session_id
-            );
-            return return_early(into_api_error(error.into()));
-        }
+    let account = match accounts.find_by_id(*account_id).await {
+        Ok(Some(account)) => account,
+        Ok(None) => return return_early(into_api_error(ApiErrorType::Unauthorized)),
+        Err(error) => return return_early(into_api_error(error.into())),
     };
 
     let res = HandlerResponse::Session(SessionResponse {
-        name: account_row.name,
-        preferred_lang: account_row.preferred_lang,
+        name: account.name,
+        preferred_lang: account.preferred_lang,
     });
 
     HttpResponse::Ok().json(res)
 }
 
 pub async fn set_user_language_handler(
-    db_pool: Data<PgPool>,
+    accounts: Data<dyn AccountRepository>,
     req_json_body: Json<SessionRequest>,
-    session_id: DBId,
+    account_id: AccountId,
     request: HttpRequest,
 ) -> HttpResponse {
     let into_api_error = ApiError::get_into(&request);
-    let preferred_lang_data  = match NewLangData::parse(req_json_body) {
+    let preferred_lang_data = match NewLangData::parse(req_json_body) {
         Ok(data) => data,
         Err(_) => {
             return return_early(into_api_error(ApiErrorType::BadRequest));
         }
     };
-    let update_result = match query!(
-        // language=postgresql
-        r#"
-        UPDATE account a SET
-            preferred_language = $1::lang
-        FROM session s
-        WHERE s.id = $2
-            AND a.id = s.account_id
-        RETURNING
-            name,
-            preferred_language AS "preferred_lang: Lang"
-        "#,
-        preferred_lang_data.into_inner() as Lang,
-        *session_id,)
-            .fetch_one(&**db_pool).await {
-        Ok(row) => row,
+    let account = match accounts
+        .set_preferred_lang(*account_id, preferred_lang_data.into_inner())
+        .await
+    {
+        Ok(Some(account)) => account,
+        Ok(None) => return return_early(into_api_error(ApiErrorType::Unauthorized)),
         Err(_error) => {
             return return_early(into_api_error(ApiErrorType::DbError));
         }
-
     };
     let res = HandlerResponse::Session(SessionResponse {
-        name: update_result.name,
-        preferred_lang: update_result.preferred_lang,
+        name: account.name,
+        preferred_lang: account.preferred_lang,
     });
 
     HttpResponse::Ok().json(res)
 }
 
 pub async fn logout_handler(
-    db_pool: Data<PgPool>,
+    sessions: Data<dyn SessionStore>,
     session_id: DBId,
     request: HttpRequest,
 ) -> HttpResponse {
     let into_api_error = ApiError::get_into(&request);
-    let _update_result = match query!(
-        // language=postgresql
-        r#"
-        DELETE FROM session WHERE id = $1
-        "#,
-        *session_id,)
-        .execute(&**db_pool).await{
-        Ok(result) => result,
-        Err(_error) => {
-            return return_early(into_api_error(ApiErrorType::DbError));
-        }
-    };
+    if sessions.delete(*session_id).await.is_err() {
+        return return_early(into_api_error(ApiErrorType::DbError));
+    }
     let res = HandlerResponse::None();
 
     HttpResponse::Ok().json(res)
@@ -152,6 +103,5 @@ impl NewLangData {
         } else {
             Err(NewLangDataError("Unknown or missing option".to_string()))
         }
-
     }
 }
@@ -1,9 +1,251 @@
-use actix_files::NamedFile;
+use crate::configuration::StaticSettings;
+use crate::security_headers::CspNonce;
+#[cfg(not(feature = "embed-client"))]
+use actix_files::{file_extension_to_mime, NamedFile};
+use actix_web::http::header::{self, ContentEncoding, ContentType, HeaderValue};
+use actix_web::http::Method;
+use actix_web::web::{Data, Path};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use regex::Regex;
+use std::path::{Path as FilePath, PathBuf};
+use std::sync::OnceLock;
 
-pub async fn return_favicon() -> Result<NamedFile, std::io::Error> {
-    NamedFile::open("../public/favicon.ico")
+/// `Cache-Control` of files with a content hash in their name, like `elm.3f2a9c1b.js`.
+const FINGERPRINTED_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+/// Precompressed siblings, in order of preference.
+pub(super) const PRECOMPRESSED: [(&str, ContentEncoding); 2] = [
+    ("br", ContentEncoding::Brotli),
+    ("gz", ContentEncoding::Gzip),
+];
+
+pub async fn return_favicon(request: HttpRequest, settings: Data<StaticSettings>) -> HttpResponse {
+    serve_file(
+        &request,
+        &settings,
+        "favicon.ico",
+        PathBuf::from("favicon.ico"),
+    )
+}
+
+pub async fn return_index(request: HttpRequest, settings: Data<StaticSettings>) -> HttpResponse {
+    let nonce = request.extensions().get::<CspNonce>().cloned();
+    match nonce {
+        Some(nonce) => serve_index_with_nonce(&settings, &nonce),
+        None => serve_file(
+            &request,
+            &settings,
+            "index.html",
+            PathBuf::from("index.html"),
+        ),
+    }
+}
+
+/// Serves a file from one of the asset directories below `public_dir`.
+pub async fn serve_asset(
+    request: HttpRequest,
+    path: Path<(String, String)>,
+    settings: Data<StaticSettings>,
+) -> HttpResponse {
+    let (dir, tail) = path.into_inner();
+    match relative_path(&tail) {
+        Some(relative_path) => serve_file(
+            &request,
+            &settings,
+            &dir,
+            PathBuf::from(&dir).join(relative_path),
+        ),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Serves `index.html` for routes of the client like `/settings/profile`, so that deep links
+/// work. Anything else, including missing asset files, is answered with a 404.
+pub async fn spa_fallback(request: HttpRequest, settings: Data<StaticSettings>) -> HttpResponse {
+    if is_client_route(&request) {
+        return_index(request, settings).await
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+/// Serves `relative_path` below `public_dir` with the `Cache-Control` configured for
+/// `cache_key`, preferring a precompressed sibling if the client accepts it.
+fn serve_file(
+    request: &HttpRequest,
+    settings: &StaticSettings,
+    cache_key: &str,
+    relative_path: PathBuf,
+) -> HttpResponse {
+    let mut response = match respond_with_file(request, settings, &relative_path) {
+        Some(response) => response,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    set_cache_control(&mut response, settings, cache_key, &relative_path);
+    if settings.precompressed {
+        response
+            .headers_mut()
+            .append(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+    }
+    response
+}
+
+/// Serves `index.html` with the CSP nonce of the request added to its `<script>` tags. As the
+/// content differs per request, it is sent without validators, so a cached copy with an
+/// outdated nonce is never reused.
+fn serve_index_with_nonce(settings: &StaticSettings, nonce: &CspNonce) -> HttpResponse {
+    let relative_path = FilePath::new("index.html");
+    let index = match read_file(settings, relative_path) {
+        Some(index) => index,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    let html = String::from_utf8_lossy(&index)
+        .replace("<script", &format!("<script nonce=\"{}\"", nonce.as_ref()));
+    let mut response = HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html);
+    set_cache_control(&mut response, settings, "index.html", relative_path);
+    response
+}
+
+fn set_cache_control(
+    response: &mut HttpResponse,
+    settings: &StaticSettings,
+    cache_key: &str,
+    relative_path: &FilePath,
+) {
+    let cache_control = if is_fingerprinted(relative_path) {
+        Some(FINGERPRINTED_CACHE_CONTROL)
+    } else {
+        settings.cache_control.get(cache_key).map(String::as_str)
+    };
+    if let Some(value) = cache_control.and_then(|value| HeaderValue::from_str(value).ok()) {
+        response.headers_mut().insert(header::CACHE_CONTROL, value);
+    }
+}
+
+/// Serves the file from disk, `NamedFile` takes care of strong ETags and conditional requests.
+#[cfg(not(feature = "embed-client"))]
+fn respond_with_file(
+    request: &HttpRequest,
+    settings: &StaticSettings,
+    relative_path: &FilePath,
+) -> Option<HttpResponse> {
+    let path = settings.public_dir.join(relative_path);
+    open_file(request, settings, &path)
+        .ok()
+        .map(|file| file.into_response(request))
+}
+
+/// Serves the file from the client embedded into the binary.
+#[cfg(feature = "embed-client")]
+fn respond_with_file(
+    request: &HttpRequest,
+    settings: &StaticSettings,
+    relative_path: &FilePath,
+) -> Option<HttpResponse> {
+    super::embedded::respond(
+        request,
+        settings.precompressed,
+        &embedded_path(relative_path)?,
+    )
+}
+
+/// Embedded files are always keyed with `/` as separator.
+#[cfg(feature = "embed-client")]
+fn embedded_path(relative_path: &FilePath) -> Option<String> {
+    let segments = relative_path
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(segments.join("/"))
+}
+
+#[cfg(not(feature = "embed-client"))]
+fn read_file(settings: &StaticSettings, relative_path: &FilePath) -> Option<Vec<u8>> {
+    std::fs::read(settings.public_dir.join(relative_path)).ok()
+}
+
+#[cfg(feature = "embed-client")]
+fn read_file(_settings: &StaticSettings, relative_path: &FilePath) -> Option<Vec<u8>> {
+    super::embedded::read(&embedded_path(relative_path)?)
+}
+
+#[cfg(not(feature = "embed-client"))]
+fn open_file(
+    request: &HttpRequest,
+    settings: &StaticSettings,
+    path: &FilePath,
+) -> std::io::Result<NamedFile> {
+    if !path.is_file() {
+        return Err(std::io::ErrorKind::NotFound.into());
+    }
+    if settings.precompressed {
+        for (extension, encoding) in PRECOMPRESSED {
+            let mut sibling = path.as_os_str().to_owned();
+            sibling.push(".");
+            sibling.push(extension);
+            if accepts_encoding(request, encoding.as_str()) && FilePath::new(&sibling).is_file() {
+                let original_extension = path
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .unwrap_or("");
+                return Ok(NamedFile::open(sibling)?
+                    .set_content_type(file_extension_to_mime(original_extension))
+                    .set_content_encoding(encoding)
+                    .disable_content_disposition());
+            }
+        }
+    }
+    NamedFile::open(path)
+}
+
+pub(super) fn accepts_encoding(request: &HttpRequest, encoding: &str) -> bool {
+    request
+        .headers()
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| {
+            accept.split(',').any(|item| {
+                let mut parts = item.split(';').map(str::trim);
+                parts.next() == Some(encoding)
+                    && parts.all(|parameter| parameter.replace(' ', "") != "q=0")
+            })
+        })
+}
+
+/// Turns the tail of an asset URL into a path, refusing anything that could leave the
+/// directory or reveal hidden files.
+fn relative_path(tail: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for segment in tail.split('/').filter(|segment| !segment.is_empty()) {
+        if segment.starts_with('.') || segment.contains('\\') {
+            return None;
+        }
+        path.push(segment);
+    }
+    Some(path).filter(|path| path.components().next().is_some())
+}
+
+fn is_fingerprinted(path: &FilePath) -> bool {
+    static FINGERPRINT: OnceLock<Regex> = OnceLock::new();
+    let fingerprint = FINGERPRINT.get_or_init(|| Regex::new(r"\.[0-9a-f]{8,}\.").unwrap());
+    path.file_name()
+        .and_then(|file_name| file_name.to_str())
+        .is_some_and(|file_name| fingerprint.is_match(file_name))
 }
 
-pub async fn return_index() -> Result<NamedFile, std::io::Error> {
-    NamedFile::open("../public/index.html")
+fn is_client_route(request: &HttpRequest) -> bool {
+    let path = request.path();
+    let is_get = request.method() == Method::GET || request.method() == Method::HEAD;
+    let accepts_html = request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    let is_api = path == "/api" || path.starts_with("/api/");
+    let is_asset = path
+        .rsplit('/')
+        .next()
+        .is_some_and(|segment| segment.contains('.'));
+    is_get && accepts_html && !is_api && !is_asset
 }
@@ -0,0 +1,182 @@
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::{Error, HttpMessage};
+use base64::engine::general_purpose;
+use base64::Engine;
+use futures_util::future::LocalBoxFuture;
+use log::{log, Level};
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use uuid::Uuid;
+
+use crate::configuration::SecurityHeadersSettings;
+
+/// Placeholder in the configured Content-Security-Policy replaced by the nonce of the request.
+const NONCE_PLACEHOLDER: &str = "{nonce}";
+
+/// Nonce allowing the inline scripts of `index.html` for one request.
+#[derive(Clone, Debug)]
+pub struct CspNonce(String);
+
+impl AsRef<str> for CspNonce {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Adds the configured security headers to every response.
+pub struct SecurityHeaders {
+    headers: Rc<Headers>,
+}
+
+struct Headers {
+    content_security_policy: String,
+    /// Headers sent with every response, apart from the Content-Security-Policy.
+    common: Vec<(HeaderName, HeaderValue)>,
+    /// Path prefixes with the headers replacing the common ones, longest prefix first.
+    /// An empty value removes the header.
+    overrides: Vec<(String, Vec<(HeaderName, String)>)>,
+}
+
+impl SecurityHeaders {
+    pub fn new(settings: &SecurityHeadersSettings, tls_enabled: bool) -> Self {
+        let mut common = vec![
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            (header::REFERRER_POLICY, settings.referrer_policy.clone()),
+            (header::X_FRAME_OPTIONS, settings.frame_options.clone()),
+            (
+                HeaderName::from_static("permissions-policy"),
+                settings.permissions_policy.clone(),
+            ),
+        ];
+        if tls_enabled {
+            common.push((header::STRICT_TRANSPORT_SECURITY, settings.hsts.clone()));
+        }
+        let common = common
+            .into_iter()
+            .filter(|(_, value)| !value.is_empty())
+            .filter_map(|(name, value)| Some((name, header_value(&value)?)))
+            .collect();
+
+        let mut overrides: Vec<(String, Vec<(HeaderName, String)>)> = settings
+            .overrides
+            .iter()
+            .map(|(prefix, headers)| {
+                let headers = headers
+                    .iter()
+                    .filter_map(|(name, value)| match HeaderName::try_from(name.as_str()) {
+                        Ok(name) => Some((name, value.clone())),
+                        Err(_) => {
+                            log!(Level::Error, "Error: Invalid header name: {:?}", name);
+                            None
+                        }
+                    })
+                    .collect();
+                (prefix.clone(), headers)
+            })
+            .collect();
+        overrides.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+
+        SecurityHeaders {
+            headers: Rc::new(Headers {
+                content_security_policy: settings.content_security_policy.clone(),
+                common,
+                overrides,
+            }),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for SecurityHeaders
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = SecurityHeadersMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SecurityHeadersMiddleware {
+            service: service.into(),
+            headers: self.headers.clone(),
+        }))
+    }
+}
+
+pub struct SecurityHeadersMiddleware<S> {
+    service: Rc<S>,
+    headers: Rc<Headers>,
+}
+
+impl<S, B> Service<ServiceRequest> for SecurityHeadersMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+        let headers = self.headers.clone();
+
+        let nonce = general_purpose::STANDARD.encode(Uuid::new_v4().as_bytes());
+        req.extensions_mut().insert(CspNonce(nonce.clone()));
+        let path = req.path().to_owned();
+
+        Box::pin(async move {
+            let mut res = srv.call(req).await?;
+            let response_headers = res.headers_mut();
+            if let Some(value) = header_value(
+                &headers
+                    .content_security_policy
+                    .replace(NONCE_PLACEHOLDER, &nonce),
+            ) {
+                response_headers.insert(header::CONTENT_SECURITY_POLICY, value);
+            }
+            for (name, value) in &headers.common {
+                response_headers.insert(name.clone(), value.clone());
+            }
+            if let Some((_, overrides)) = headers
+                .overrides
+                .iter()
+                .find(|(prefix, _)| path.starts_with(prefix.as_str()))
+            {
+                apply_overrides(response_headers, overrides, &nonce);
+            }
+            Ok(res)
+        })
+    }
+}
+
+fn apply_overrides(
+    response_headers: &mut HeaderMap,
+    overrides: &[(HeaderName, String)],
+    nonce: &str,
+) {
+    for (name, value) in overrides {
+        if value.is_empty() {
+            response_headers.remove(name);
+        } else if let Some(value) = header_value(&value.replace(NONCE_PLACEHOLDER, nonce)) {
+            response_headers.insert(name.clone(), value);
+        }
+    }
+}
+
+fn header_value(value: &str) -> Option<HeaderValue> {
+    match HeaderValue::from_str(value) {
+        Ok(value) => Some(value),
+        Err(_) => {
+            log!(Level::Error, "Error: Invalid header value: {:?}", value);
+            None
+        }
+    }
+}
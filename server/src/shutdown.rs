@@ -0,0 +1,93 @@
+use actix_web::rt::task::JoinHandle;
+use actix_web::rt::time::timeout;
+use log::{log, Level};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Resolves with the name of the signal once SIGTERM or SIGINT is received.
+pub async fn signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("Couldn't listen for SIGTERM");
+        tokio::select! {
+            _ = terminate.recv() => "SIGTERM",
+            _ = tokio::signal::ctrl_c() => "SIGINT",
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "SIGINT"
+    }
+}
+
+/// Handed to background tasks, resolves `recv` once they have to stop.
+#[derive(Clone)]
+pub struct ShutdownSignal(watch::Receiver<bool>);
+
+impl ShutdownSignal {
+    pub async fn recv(&mut self) {
+        // an error means the sender is gone, which is a shutdown as well
+        let _ = self.0.wait_for(|stopping| *stopping).await;
+    }
+}
+
+/// Tasks running next to the server, stopped after it has finished draining requests.
+pub struct BackgroundTasks {
+    sender: watch::Sender<bool>,
+    tasks: Vec<(&'static str, JoinHandle<()>)>,
+}
+
+impl Default for BackgroundTasks {
+    fn default() -> Self {
+        BackgroundTasks {
+            sender: watch::Sender::new(false),
+            tasks: Vec::new(),
+        }
+    }
+}
+
+impl BackgroundTasks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn<F, Fut>(&mut self, name: &'static str, task: F)
+    where
+        F: FnOnce(ShutdownSignal) -> Fut,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let signal = ShutdownSignal(self.sender.subscribe());
+        self.tasks.push((name, actix_web::rt::spawn(task(signal))));
+    }
+
+    /// Signals all tasks to stop and waits up to `grace` for them, aborting the rest.
+    pub async fn stop(self, grace: Duration) {
+        self.sender.send_replace(true);
+        let deadline = Instant::now() + grace;
+        for (name, mut task) in self.tasks {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match timeout(remaining, &mut task).await {
+                Ok(Ok(())) => (),
+                Ok(Err(error)) => {
+                    log!(
+                        Level::Error,
+                        "Error: {}, in background task {}",
+                        error,
+                        name
+                    );
+                }
+                Err(_) => {
+                    log!(
+                        Level::Warn,
+                        "Error: Background task {} didn't stop in time, aborting it",
+                        name
+                    );
+                    task.abort();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use actix_web::dev::Server;
+use actix_web::middleware::Condition;
+use actix_web::rt::time::sleep;
+use actix_web::web::Data;
+use actix_web::{web, HttpMessage, HttpServer};
+use anyhow::Context;
+use futures_util::future::{join_all, try_join};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::access_log::AccessLog;
+use crate::api_error::{ApiError, ApiErrorType};
+use crate::authorisation::Authorisation;
+use crate::clock::{Clock, SystemClock};
+use crate::configuration::Settings;
+use crate::metrics::{Metrics, RequestMetrics};
+use crate::repository::{AccountRepository, PgAccountRepository};
+use crate::routes::{ExpiresAt, Readiness};
+use crate::security_headers::SecurityHeaders;
+use crate::shutdown::BackgroundTasks;
+use crate::tls::RedirectToHttps;
+use crate::{cors, database, repository, routes, shutdown, tls};
+
+/// The server with its database pool and background tasks, listening but not yet stopping on
+/// signals.
+pub struct Application {
+    port: u16,
+    server: Server,
+    admin_server: Option<Server>,
+    db_pool: PgPool,
+    readiness: Data<Readiness>,
+    background_tasks: BackgroundTasks,
+    shutdown_delay: Duration,
+    shutdown_timeout: Duration,
+}
+
+impl Application {
+    /// Connects to the database, prepares its schema and binds the listeners, so that requests
+    /// are served from here on. An `application_port` of 0 binds a free port, see `port`.
+    pub async fn build(settings: Settings) -> anyhow::Result<Application> {
+        Self::build_with_clock(settings, Arc::new(SystemClock)).await
+    }
+
+    /// Like `build`, with session expiry going by `clock`, e.g. a `FakeClock` in tests.
+    pub async fn build_with_clock(
+        settings: Settings,
+        clock: Arc<dyn Clock>,
+    ) -> anyhow::Result<Application> {
+        let db_pool = database::connect_with_retry(&settings.database)
+            .await
+            .context("Couldn't connect to database")?;
+        database::prepare_schema(&db_pool, settings.database.migrations)
+            .await
+            .context("Couldn't prepare the database schema")?;
+
+        let session_secret = bytes::Bytes::from(settings.session_secret.expose().clone());
+        let access_log_settings = settings.log.access;
+        let json_parse_config = web::JsonConfig::default()
+            .limit(512)
+            .content_type(|mime| mime == "application/json")
+            .content_type_required(true)
+            .error_handler(|_, req| {
+                let api_error = ApiError::get_into(req)(ApiErrorType::BadRequest);
+                req.extensions_mut().insert(api_error.clone());
+                req.extensions_mut().insert::<ExpiresAt>(0);
+                api_error.error.into()
+            });
+
+        let static_settings = settings.static_files;
+
+        let server_settings = settings.server;
+        let port = settings.application_port;
+        let tls_config = match &server_settings.tls {
+            Some(tls_settings) => {
+                Some(tls::server_config(tls_settings).context("Couldn't load TLS configuration")?)
+            }
+            None => None,
+        };
+        let redirect_port = server_settings
+            .tls
+            .as_ref()
+            .and_then(|tls_settings| tls_settings.redirect_port);
+
+        let readiness = Data::new(Readiness::default());
+        let metrics_settings = settings.metrics;
+        let metrics = Metrics::new().context("Couldn't register metrics")?;
+        // `/metrics` is only served on the application port if there is no admin port for it
+        let serve_metrics = metrics_settings.enabled && metrics_settings.admin_port.is_none();
+        let security_headers_settings = settings.security_headers;
+        let cors_settings = settings.cors;
+        let tls_enabled = tls_config.is_some();
+
+        let accounts: Arc<dyn AccountRepository> =
+            Arc::new(PgAccountRepository::new(db_pool.clone()));
+        let session_settings = settings.sessions;
+        let sessions = repository::session_store(&session_settings, &db_pool)
+            .await
+            .context("Couldn't create the session store")?;
+        let purged_sessions = sessions.clone();
+        let counted_sessions = sessions.clone();
+        let app_clock = clock.clone();
+        let app_db_pool = db_pool.clone();
+        let app_metrics = metrics.clone();
+        let app_readiness = readiness.clone();
+        let mut server = HttpServer::new(move || {
+            let db_pool = app_db_pool.clone();
+            let clock = app_clock.clone();
+            let metrics = app_metrics.clone();
+            let mut scope = web::scope("")
+                .route("/healthz", web::get().to(routes::liveness_handler))
+                .route("/readyz", web::get().to(routes::readiness_handler));
+            if serve_metrics {
+                scope = scope.route("/metrics", web::get().to(routes::metrics_handler));
+            }
+            actix_web::App::new()
+                .wrap(Condition::new(
+                    security_headers_settings.enabled,
+                    SecurityHeaders::new(&security_headers_settings, tls_enabled),
+                ))
+                .wrap(Condition::new(
+                    redirect_port.is_some(),
+                    RedirectToHttps::new(port),
+                ))
+                .wrap(AccessLog::new(access_log_settings.clone()))
+                .wrap(Condition::new(
+                    metrics_settings.enabled,
+                    RequestMetrics::new(metrics.clone()),
+                ))
+                .app_data(Data::new(db_pool))
+                .app_data(Data::from(accounts.clone()))
+                .app_data(Data::from(sessions.clone()))
+                .app_data(Data::from(clock.clone()))
+                .app_data(Data::new(metrics))
+                .app_data(Data::new(static_settings.clone()))
+                .app_data(app_readiness.clone())
+                .service(
+                    scope
+                        .route(
+                            "/{dir:js|css|img|lang}/{tail:.*}",
+                            web::get().to(routes::serve_asset),
+                        )
+                        .service(
+                            web::scope("/api")
+                                .app_data(Data::new(session_secret.clone()))
+                                .app_data(json_parse_config.clone())
+                                .wrap(Authorisation)
+                                .wrap(Condition::new(
+                                    !cors_settings.allowed_origins.is_empty(),
+                                    cors::cors(&cors_settings),
+                                ))
+                                .route("/login", web::post().to(routes::login_handler))
+                                .route("/session", web::get().to(routes::session_handler))
+                                .route(
+                                    "/session",
+                                    web::post().to(routes::set_user_language_handler),
+                                )
+                                .route("/session", web::delete().to(routes::logout_handler))
+                                .route("/{route:.*}", web::get().to(routes::not_found_handler)),
+                        )
+                        .route("/favicon.ico", web::get().to(routes::return_favicon))
+                        .route("/", web::get().to(routes::return_index))
+                        .default_service(web::to(routes::spa_fallback)),
+                )
+        })
+        .keep_alive(Duration::from_secs(server_settings.keep_alive_seconds))
+        .shutdown_timeout(server_settings.shutdown_timeout_seconds);
+        if let Some(workers) = server_settings.workers {
+            server = server.workers(workers);
+        }
+        for host in &server_settings.hosts {
+            let address = (host.as_str(), port);
+            server = match &tls_config {
+                Some(tls_config) => server.bind_rustls_0_23(address, tls_config.clone()),
+                None => server.bind(address),
+            }
+            .with_context(|| format!("Couldn't bind to {}:{}", host, port))?;
+            if let Some(redirect_port) = redirect_port {
+                server = server
+                    .bind((host.as_str(), redirect_port))
+                    .with_context(|| format!("Couldn't bind to {}:{}", host, redirect_port))?;
+            }
+        }
+        let port = server
+            .addrs()
+            .first()
+            .map_or(port, |address| address.port());
+        let server = server.disable_signals().run();
+
+        let admin_server = match metrics_settings.admin_port {
+            Some(admin_port) if metrics_settings.enabled => {
+                let admin_db_pool = db_pool.clone();
+                let admin_metrics = metrics.clone();
+                let admin_host = metrics_settings.admin_host.as_str();
+                let admin_server = HttpServer::new(move || {
+                    actix_web::App::new()
+                        .app_data(Data::new(admin_db_pool.clone()))
+                        .app_data(Data::new(admin_metrics.clone()))
+                        .route("/metrics", web::get().to(routes::metrics_handler))
+                })
+                .workers(1)
+                .disable_signals()
+                .bind((admin_host, admin_port))
+                .with_context(|| format!("Couldn't bind to {}:{}", admin_host, admin_port))?;
+                Some(admin_server.run())
+            }
+            _ => None,
+        };
+
+        let mut background_tasks = BackgroundTasks::new();
+        {
+            let metrics = metrics.clone();
+            let clock = clock.clone();
+            let interval = Duration::from_secs(session_settings.purge_interval_seconds);
+            background_tasks.spawn("session purge", move |shutdown| {
+                repository::purge_expired_sessions(
+                    purged_sessions,
+                    clock,
+                    metrics,
+                    interval,
+                    shutdown,
+                )
+            });
+        }
+        if metrics_settings.enabled {
+            let db_pool = db_pool.clone();
+            background_tasks.spawn("metrics", move |shutdown| {
+                metrics.refresh(db_pool, counted_sessions, clock, shutdown)
+            });
+        }
+
+        Ok(Application {
+            port,
+            server,
+            admin_server,
+            db_pool,
+            readiness,
+            background_tasks,
+            shutdown_delay: Duration::from_secs(server_settings.shutdown_delay_seconds),
+            shutdown_timeout: Duration::from_secs(server_settings.shutdown_timeout_seconds),
+        })
+    }
+
+    /// The port actually bound for `application_port`.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn db_pool(&self) -> &PgPool {
+        &self.db_pool
+    }
+
+    /// Serves until SIGTERM or SIGINT, then drains requests, stops the background tasks and
+    /// closes the database pool.
+    pub async fn run_until_stopped(self) -> std::io::Result<()> {
+        let mut server_handles = vec![self.server.handle()];
+        if let Some(admin_server) = &self.admin_server {
+            server_handles.push(admin_server.handle());
+        }
+        let readiness = self.readiness;
+        let shutdown_delay = self.shutdown_delay;
+        actix_web::rt::spawn(async move {
+            let signal = shutdown::signal().await;
+            log::info!("Received {}, shutting down", signal);
+            readiness.set_shutting_down();
+            sleep(shutdown_delay).await;
+            let graceful = join_all(server_handles.iter().map(|handle| handle.stop(true)));
+            tokio::select! {
+                _ = graceful => (),
+                signal = shutdown::signal() => {
+                    log::warn!("Received {} again, stopping without draining requests", signal);
+                    join_all(server_handles.iter().map(|handle| handle.stop(false))).await;
+                }
+            }
+        });
+
+        let result = match self.admin_server {
+            Some(admin_server) => try_join(self.server, admin_server).await.map(|_| ()),
+            None => self.server.await,
+        };
+
+        self.background_tasks.stop(self.shutdown_timeout).await;
+        self.db_pool.close().await;
+        log::info!("Shut down");
+        log::logger().flush();
+        result
+    }
+}
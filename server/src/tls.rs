@@ -0,0 +1,111 @@
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header;
+use actix_web::{Error, HttpResponse};
+use anyhow::{anyhow, Context};
+use futures_util::future::LocalBoxFuture;
+use rustls::ServerConfig;
+use std::fs::File;
+use std::future::{ready, Ready};
+use std::io::BufReader;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::configuration::TlsSettings;
+
+/// Builds the rustls configuration from the PEM encoded certificate chain and private key.
+pub fn server_config(settings: &TlsSettings) -> anyhow::Result<ServerConfig> {
+    let cert_file = File::open(&settings.cert_path)
+        .with_context(|| format!("Couldn't open certificate {:?}", settings.cert_path))?;
+    let key_file = File::open(&settings.key_path)
+        .with_context(|| format!("Couldn't open private key {:?}", settings.key_path))?;
+    let cert_chain = rustls_pemfile::certs(&mut BufReader::new(cert_file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Couldn't read certificate {:?}", settings.cert_path))?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(key_file))
+        .with_context(|| format!("Couldn't read private key {:?}", settings.key_path))?
+        .ok_or_else(|| anyhow!("No private key found in {:?}", settings.key_path))?;
+    let config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(cert_chain, key)?;
+    Ok(config)
+}
+
+/// Redirects requests that came in on a plain HTTP listener to the HTTPS port.
+pub struct RedirectToHttps {
+    https_port: u16,
+}
+
+impl RedirectToHttps {
+    pub fn new(https_port: u16) -> Self {
+        RedirectToHttps { https_port }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RedirectToHttps
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RedirectToHttpsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RedirectToHttpsMiddleware {
+            service: service.into(),
+            https_port: self.https_port,
+        }))
+    }
+}
+
+pub struct RedirectToHttpsMiddleware<S> {
+    service: Rc<S>,
+    https_port: u16,
+}
+
+impl<S, B> Service<ServiceRequest> for RedirectToHttpsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+
+        if req.app_config().secure() {
+            return Box::pin(async move { Ok(srv.call(req).await?.map_into_left_body()) });
+        }
+
+        let host = req.connection_info().host().to_owned();
+        // strip the port of the plain listener, keeping IPv6 addresses intact
+        let host_name = match host.rsplit_once(':') {
+            Some((name, port)) if !port.contains(']') => name.to_owned(),
+            _ => host,
+        };
+        let path_and_query = req
+            .uri()
+            .path_and_query()
+            .map(|path_and_query| path_and_query.as_str())
+            .unwrap_or("/");
+        let location = match self.https_port {
+            443 => format!("https://{}{}", host_name, path_and_query),
+            port => format!("https://{}:{}{}", host_name, port, path_and_query),
+        };
+        let res = HttpResponse::PermanentRedirect()
+            .insert_header((header::LOCATION, location))
+            .finish();
+        Box::pin(async move { Ok(req.into_response(res).map_into_right_body()) })
+    }
+}
@@ -0,0 +1,159 @@
+use reqwest::header::AUTHORIZATION;
+use reqwest::Client;
+use serde_json::{json, Value};
+use server::clock::FakeClock;
+use server::configuration::{load_configuration, Profile};
+use server::startup::Application;
+use sqlx::postgres::PgConnectOptions;
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub const ACCOUNT_NAME: &str = "tester";
+pub const NAME: &str = "Test User";
+pub const PASSWORD: &str = "correct horse";
+/// Lowest cost bcrypt accepts, hashing with the default one takes too long for tests.
+const BCRYPT_COST: u32 = 4;
+
+/// A running server with a database of its own, see `spawn_app`.
+pub struct TestApp {
+    pub address: String,
+    pub db_pool: PgPool,
+    pub client: Client,
+    /// The time of the server, standing still until advanced.
+    pub clock: Arc<FakeClock>,
+    database_name: String,
+    maintenance_options: PgConnectOptions,
+}
+
+/// Creates a database named after the one of the `test` profile with a random suffix, starts
+/// the server on it at a free port and adds the account `ACCOUNT_NAME`.
+///
+/// The database user needs the `CREATEDB` privilege. Call `TestApp::cleanup` at the end of
+/// the test to drop the database again.
+pub async fn spawn_app() -> TestApp {
+    let mut settings =
+        load_configuration(Profile::Test, None).expect("Couldn't read the configuration");
+    settings.application_port = 0;
+    settings.server.hosts = vec!["127.0.0.1".to_string()];
+    settings.server.shutdown_delay_seconds = 0;
+    settings.metrics.enabled = false;
+    // so that changes to the `session` table take effect immediately
+    settings.sessions.cache_ttl_seconds = 0;
+
+    let maintenance_options = settings.database.connect_options();
+    let database_name = format!(
+        "{}_{}",
+        settings.database.database_name,
+        Uuid::new_v4().simple()
+    );
+    let mut connection = PgConnection::connect_with(&maintenance_options)
+        .await
+        .expect("Couldn't connect to Postgres");
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}""#, database_name).as_str())
+        .await
+        .expect("Couldn't create the test database");
+    settings.database.database_name = database_name.clone();
+
+    let clock = Arc::new(FakeClock::default());
+    let application = Application::build_with_clock(settings, clock.clone())
+        .await
+        .expect("Couldn't build the application");
+    let app = TestApp {
+        address: format!("http://127.0.0.1:{}", application.port()),
+        db_pool: application.db_pool().clone(),
+        client: Client::new(),
+        clock,
+        database_name,
+        maintenance_options,
+    };
+    actix_web::rt::spawn(application.run_until_stopped());
+
+    app.add_account(ACCOUNT_NAME, NAME, PASSWORD).await;
+    app
+}
+
+impl TestApp {
+    pub async fn add_account(&self, account_name: &str, name: &str, password: &str) -> Uuid {
+        sqlx::query_scalar(
+            "INSERT INTO account (account_name, pw_hash, name) VALUES ($1, $2, $3) RETURNING id",
+        )
+        .bind(account_name)
+        .bind(bcrypt::hash(password, BCRYPT_COST).unwrap())
+        .bind(name)
+        .fetch_one(&self.db_pool)
+        .await
+        .expect("Couldn't add account")
+    }
+
+    pub async fn post_login(&self, account: &str, password: &str) -> Value {
+        self.client
+            .post(format!("{}/api/login", self.address))
+            .json(&json!({ "account": account, "pw": password }))
+            .send()
+            .await
+            .expect("Couldn't send request")
+            .json()
+            .await
+            .expect("Response isn't JSON")
+    }
+
+    /// Logs in as `ACCOUNT_NAME`, returning the session token.
+    pub async fn login(&self) -> String {
+        let body = self.post_login(ACCOUNT_NAME, PASSWORD).await;
+        body["data"]["Login"]["session_token"]
+            .as_str()
+            .expect("Login failed")
+            .to_string()
+    }
+
+    pub async fn get_session(&self, token: &str) -> Value {
+        self.client
+            .get(format!("{}/api/session", self.address))
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+            .send()
+            .await
+            .expect("Couldn't send request")
+            .json()
+            .await
+            .expect("Response isn't JSON")
+    }
+
+    pub async fn post_language(&self, token: &str, language: &str) -> Value {
+        self.client
+            .post(format!("{}/api/session", self.address))
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+            .json(&json!({ "preferred_lang": language }))
+            .send()
+            .await
+            .expect("Couldn't send request")
+            .json()
+            .await
+            .expect("Response isn't JSON")
+    }
+
+    pub async fn delete_session(&self, token: &str) -> Value {
+        self.client
+            .delete(format!("{}/api/session", self.address))
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+            .send()
+            .await
+            .expect("Couldn't send request")
+            .json()
+            .await
+            .expect("Response isn't JSON")
+    }
+
+    /// Drops the database of the test, which is left behind if the test panics before.
+    pub async fn cleanup(self) {
+        self.db_pool.close().await;
+        let mut connection = PgConnection::connect_with(&self.maintenance_options)
+            .await
+            .expect("Couldn't connect to Postgres");
+        connection
+            .execute(format!(r#"DROP DATABASE "{}" WITH (FORCE)"#, self.database_name).as_str())
+            .await
+            .expect("Couldn't drop the test database");
+    }
+}
@@ -0,0 +1,46 @@
+use chrono::Duration;
+use server::clock::Clock;
+
+use crate::helpers::{spawn_app, ACCOUNT_NAME, PASSWORD};
+
+#[actix_web::test]
+async fn login_returns_session_token() {
+    let app = spawn_app().await;
+
+    let body = app.post_login(ACCOUNT_NAME, PASSWORD).await;
+
+    assert_eq!(body["error"], "");
+    assert!(body["data"]["Login"]["session_token"].is_string());
+    let expires_at = body["expires_at"].as_i64().unwrap();
+    assert_eq!(
+        expires_at,
+        (app.clock.now() + Duration::minutes(30)).timestamp()
+    );
+    let sessions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM session")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(sessions, 1);
+    app.cleanup().await;
+}
+
+#[actix_web::test]
+async fn login_with_wrong_password_is_unauthorized() {
+    let app = spawn_app().await;
+
+    let body = app.post_login(ACCOUNT_NAME, "wrong password").await;
+
+    assert_eq!(body["error"], "Unauthorized");
+    assert_eq!(body["expires_at"], 0);
+    app.cleanup().await;
+}
+
+#[actix_web::test]
+async fn login_with_unknown_account_is_unauthorized() {
+    let app = spawn_app().await;
+
+    let body = app.post_login("nobody", PASSWORD).await;
+
+    assert_eq!(body["error"], "Unauthorized");
+    app.cleanup().await;
+}
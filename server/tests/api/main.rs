@@ -0,0 +1,5 @@
+//! Runs the API against a database created for each test on the server of the `test` profile.
+
+mod helpers;
+mod login;
+mod session;
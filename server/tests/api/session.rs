@@ -0,0 +1,100 @@
+use chrono::Duration;
+use serde_json::{json, Value};
+
+use crate::helpers::{spawn_app, NAME};
+
+#[actix_web::test]
+async fn session_returns_account() {
+    let app = spawn_app().await;
+    let token = app.login().await;
+
+    let body = app.get_session(&token).await;
+
+    assert_eq!(body["error"], "");
+    assert_eq!(body["data"]["Session"]["name"], NAME);
+    assert_eq!(body["data"]["Session"]["preferred_lang"], "De");
+    app.cleanup().await;
+}
+
+#[actix_web::test]
+async fn session_without_token_is_unauthorized() {
+    let app = spawn_app().await;
+
+    let body: Value = app
+        .client
+        .get(format!("{}/api/session", app.address))
+        .send()
+        .await
+        .expect("Couldn't send request")
+        .json()
+        .await
+        .expect("Response isn't JSON");
+
+    assert_eq!(body["error"], "Unauthorized");
+    assert_eq!(body["data"], json!({ "None": [] }));
+    app.cleanup().await;
+}
+
+#[actix_web::test]
+async fn session_with_malformed_token_is_unauthorized() {
+    let app = spawn_app().await;
+
+    let body = app.get_session("not a token").await;
+
+    assert_eq!(body["error"], "Unauthorized");
+    assert_eq!(body["data"], json!({ "None": [] }));
+    app.cleanup().await;
+}
+
+#[actix_web::test]
+async fn language_change_is_stored() {
+    let app = spawn_app().await;
+    let token = app.login().await;
+
+    let body = app.post_language(&token, "en").await;
+
+    assert_eq!(body["error"], "");
+    assert_eq!(body["data"]["Session"]["preferred_lang"], "En");
+    let body = app.get_session(&token).await;
+    assert_eq!(body["data"]["Session"]["preferred_lang"], "En");
+    app.cleanup().await;
+}
+
+#[actix_web::test]
+async fn unknown_language_is_a_bad_request() {
+    let app = spawn_app().await;
+    let token = app.login().await;
+
+    let body = app.post_language(&token, "fr").await;
+
+    assert_eq!(body["error"], "Bad Request");
+    let body = app.get_session(&token).await;
+    assert_eq!(body["data"]["Session"]["preferred_lang"], "De");
+    app.cleanup().await;
+}
+
+#[actix_web::test]
+async fn logout_ends_session() {
+    let app = spawn_app().await;
+    let token = app.login().await;
+
+    let body = app.delete_session(&token).await;
+
+    assert_eq!(body["error"], "");
+    let body = app.get_session(&token).await;
+    assert_eq!(body["error"], "Unauthorized");
+    app.cleanup().await;
+}
+
+#[actix_web::test]
+async fn expired_session_is_refused() {
+    let app = spawn_app().await;
+    let token = app.login().await;
+    app.clock.advance(Duration::minutes(31));
+
+    let body = app.get_session(&token).await;
+
+    assert_eq!(body["error"], "Expired");
+    assert_eq!(body["expires_at"], 0);
+    app.cleanup().await;
+}
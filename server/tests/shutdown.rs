@@ -0,0 +1,92 @@
+//! Runs the server binary against the `test` profile, so the `aes_test` database has to be
+//! reachable.
+#![cfg(unix)]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, ExitStatus};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn start_server(port: u16) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_actix-elm-setup"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("APP_PROFILE", "test")
+        .env("APP_APPLICATION_PORT", port.to_string())
+        .env("APP_SERVER__SHUTDOWN_DELAY_SECONDS", "2")
+        .env("APP_METRICS__ENABLED", "false")
+        .spawn()
+        .expect("Couldn't start the server")
+}
+
+/// Sends a `GET` request, returning the status code, or `None` if the connection failed.
+fn get_status(port: u16, path: &str) -> Option<u16> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(5))).ok()?;
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    )
+    .ok()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+    response.split(' ').nth(1)?.parse().ok()
+}
+
+fn send_signal(server: &Child, signal: &str) {
+    let status = Command::new("kill")
+        .args([signal, &server.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn wait_for_exit(server: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if let Some(status) = server.try_wait().unwrap() {
+            return Some(status);
+        }
+        sleep(Duration::from_millis(50));
+    }
+    None
+}
+
+#[test]
+fn sigterm_fails_readiness_then_stops_the_server() {
+    let port = free_port();
+    let mut server = start_server(port);
+
+    let start = Instant::now();
+    while get_status(port, "/healthz") != Some(200) {
+        if start.elapsed() > STARTUP_TIMEOUT {
+            server.kill().unwrap();
+            panic!("Server didn't start");
+        }
+        sleep(Duration::from_millis(100));
+    }
+
+    send_signal(&server, "-TERM");
+    sleep(Duration::from_millis(500));
+    // still serving during the shutdown delay, but not ready anymore
+    assert_eq!(get_status(port, "/healthz"), Some(200));
+    assert_eq!(get_status(port, "/readyz"), Some(503));
+
+    let status = wait_for_exit(&mut server, Duration::from_secs(10));
+    if status.is_none() {
+        server.kill().unwrap();
+    }
+    assert!(status.expect("Server didn't stop").success());
+    assert_eq!(get_status(port, "/healthz"), None);
+}